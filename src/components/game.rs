@@ -1,42 +1,76 @@
 use crate::components::utils;
 use crate::game::{
-    ClientGame, FootballersGame, Game, HostGame, GAME_CANVAS_HEIGHT, GAME_CANVAS_WIDTH,
+    ChatLine, ClientGame, FootballersGame, Game, HostGame, LobbyPlayer, MatchSettings, Migration,
+    TieBreak, GAME_CANVAS_HEIGHT, GAME_CANVAS_WIDTH, MAX_CHAT_MESSAGE_LENGTH, MAX_MATCH_MINUTES,
 };
-use crate::utils::global_window;
+use crate::utils::{download_file, global_window};
 use log::error;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_peers::{get_random_session_id, ConnectionType, SessionId};
-use web_sys::HtmlCanvasElement;
+use web_sys::{HtmlCanvasElement, KeyboardEvent};
 use yew::{html, Component, Context, Html, NodeRef};
 
+const CHAT_SCROLLBACK: usize = 20;
+
 #[derive(Serialize, Deserialize)]
 pub struct GameQuery {
     pub session_id: String,
     pub is_host: bool,
+    pub match_minutes: Option<u32>,
+    pub tie_break: Option<TieBreak>,
+    pub nickname: Option<String>,
 }
 
 impl GameQuery {
-    pub(crate) fn new(session_id: String, is_host: bool) -> Self {
+    pub(crate) fn new(
+        session_id: String,
+        is_host: bool,
+        match_minutes: Option<u32>,
+        tie_break: Option<TieBreak>,
+        nickname: Option<String>,
+    ) -> Self {
         GameQuery {
             session_id,
             is_host,
+            match_minutes,
+            tie_break,
+            nickname,
         }
     }
 }
 
 pub enum GameMsg {
     CopyLink,
+    DownloadReplay,
     Init,
+    Rematch,
+    SendChat,
+    StartMatch,
     Tick,
+    ToggleChatMode,
+    ToggleReady,
 }
 
 pub(crate) struct GameComponent {
     session_id: SessionId,
     is_host: bool,
+    settings: MatchSettings,
+    nickname: String,
+    // seconds other players see the match behind the host, overridable for testing bad networks
+    interpolation_delay: Option<f64>,
     canvas: NodeRef,
     game: Option<FootballersGame>,
+    game_ended: bool,
+    rematch_voted: bool,
+    lobby: Option<Vec<LobbyPlayer>>,
+    // version of the host, if the local game can't play with it
+    incompatible_host: Option<String>,
+    ready: bool,
+    chat: VecDeque<ChatLine>,
+    chat_team_only: bool,
     tick_callback: Closure<dyn FnMut()>,
 }
 
@@ -63,6 +97,23 @@ impl Component for GameComponent {
                     (generated_session_id, true)
                 }
             };
+        let settings = MatchSettings {
+            duration: query_params
+                .get("match_minutes")
+                .and_then(|minutes| minutes.parse::<u32>().ok())
+                // out of range lengths, straight from the URL, fall back to no time limit
+                .filter(|minutes| (1..=MAX_MATCH_MINUTES).contains(minutes))
+                .map(|minutes| minutes * 60),
+            tie_break: query_params
+                .get("tie_break")
+                .and_then(|tie_break| tie_break.parse().ok())
+                .unwrap_or_default(),
+        };
+        let nickname = query_params.get("nickname").unwrap_or_default();
+        let interpolation_delay = query_params
+            .get("interpolation_delay")
+            .and_then(|millis| millis.parse::<u32>().ok())
+            .map(|millis| millis as f64 / 1000.0);
         let canvas = NodeRef::default();
         let tick_callback = {
             let link = ctx.link().clone();
@@ -72,8 +123,18 @@ impl Component for GameComponent {
         Self {
             is_host,
             session_id,
+            settings,
+            nickname,
+            interpolation_delay,
             canvas,
             game: None,
+            game_ended: false,
+            rematch_voted: false,
+            lobby: None,
+            incompatible_host: None,
+            ready: false,
+            chat: VecDeque::new(),
+            chat_team_only: false,
             tick_callback,
         }
     }
@@ -86,33 +147,104 @@ impl Component for GameComponent {
                 }
                 false
             }
+            GameMsg::DownloadReplay => {
+                if let Some(FootballersGame::Host(game)) = self.game.as_ref() {
+                    let file_name = format!("footballers-replay-{}.json", self.session_id.as_str());
+                    if let Err(error) =
+                        download_file(&file_name, &game.replay_file(), "application/json")
+                    {
+                        error!("Failed downloading replay: {error:?}");
+                    }
+                }
+                false
+            }
             GameMsg::Init => {
                 self.game = Some(init_game(
                     self.canvas.clone(),
                     self.is_host,
                     self.session_id.clone(),
+                    self.settings,
+                    self.nickname.clone(),
+                    self.interpolation_delay,
+                    None,
                 ));
                 ctx.link().send_message(GameMsg::Tick);
                 false
             }
-            GameMsg::Tick => {
-                match self.game.as_mut() {
-                    Some(game) => {
-                        game.tick();
-                        if !game.ended() {
-                            if let Err(error) = global_window().request_animation_frame(
-                                self.tick_callback.as_ref().unchecked_ref(),
-                            ) {
-                                error!("Failed requesting next animation frame: {error:?}");
-                            }
-                        }
-                    }
-                    None => {
-                        error!("No initialized game object yet.");
+            GameMsg::Rematch => {
+                if let Some(game) = self.game.as_mut() {
+                    game.rematch();
+                    self.rematch_voted = !self.is_host;
+                }
+                true
+            }
+            GameMsg::SendChat => {
+                let input = utils::get_input("chat-input");
+                let text = input.value();
+                input.set_value("");
+                if let Some(game) = self.game.as_mut() {
+                    if !text.trim().is_empty() {
+                        game.send_chat(&text, self.chat_team_only);
                     }
                 }
                 false
             }
+            GameMsg::StartMatch => {
+                if let Some(game) = self.game.as_mut() {
+                    game.set_ready(true);
+                }
+                false
+            }
+            GameMsg::ToggleChatMode => {
+                self.chat_team_only = !self.chat_team_only;
+                true
+            }
+            GameMsg::ToggleReady => {
+                self.ready = !self.ready;
+                if let Some(game) = self.game.as_mut() {
+                    game.set_ready(self.ready);
+                }
+                true
+            }
+            GameMsg::Tick => match self.game.as_mut() {
+                Some(game) => {
+                    game.tick();
+                    // keep ticking after the game ends, as it can be restarted
+                    if let Err(error) = global_window()
+                        .request_animation_frame(self.tick_callback.as_ref().unchecked_ref())
+                    {
+                        error!("Failed requesting next animation frame: {error:?}");
+                    }
+                    if let Some(migration) = game.take_migration() {
+                        self.migrate(migration);
+                        return true;
+                    }
+                    // re-render only when the game ends or restarts, to toggle end of game controls
+                    let game_ended = game.ended();
+                    let changed = game_ended != self.game_ended;
+                    self.game_ended = game_ended;
+                    if !game_ended {
+                        self.rematch_voted = false;
+                    }
+                    let lobby = game.lobby();
+                    let lobby_changed = lobby != self.lobby;
+                    self.lobby = lobby;
+                    let incompatible_host = game.incompatible_host();
+                    let incompatible_host_changed = incompatible_host != self.incompatible_host;
+                    self.incompatible_host = incompatible_host;
+                    let chat = game.take_chat();
+                    let chat_changed = !chat.is_empty();
+                    self.chat.extend(chat);
+                    while self.chat.len() > CHAT_SCROLLBACK {
+                        self.chat.pop_front();
+                    }
+                    changed || lobby_changed || incompatible_host_changed || chat_changed
+                }
+                None => {
+                    error!("No initialized game object yet.");
+                    false
+                }
+            },
         }
     }
 
@@ -120,18 +252,116 @@ impl Component for GameComponent {
         let width = GAME_CANVAS_WIDTH.to_string();
         let height = GAME_CANVAS_HEIGHT.to_string();
         let onclick = ctx.link().callback(|_| GameMsg::CopyLink);
+        let download_replay = ctx.link().callback(|_| GameMsg::DownloadReplay);
+        let rematch = ctx.link().callback(|_| GameMsg::Rematch);
+        let rematch_label = if self.is_host {
+            "Rematch"
+        } else {
+            "Vote for rematch"
+        };
         html! {
             <div class="px-3">
-                <canvas id="canvas" { width } { height } ref={ self.canvas.clone() }></canvas>
+                if let Some(version) = &self.incompatible_host {
+                    <div id="incompatible_host">
+                        <p class="lead">{ format!("The host runs version {version} of the game, which doesn't work with yours.") }</p>
+                        <p class="lead">{ "Please refresh the page to get the latest version. If that doesn't help, ask the host to refresh theirs." }</p>
+                    </div>
+                }
+                <canvas id="canvas" tabindex="0" { width } { height } ref={ self.canvas.clone() }></canvas>
+                if let Some(lobby) = &self.lobby {
+                    { self.view_lobby(ctx, lobby) }
+                }
+                { self.view_chat(ctx) }
                 <p class="lead">{ "Use WASD to move, SPACE to shoot the ball." }</p>
                 <p class="lead">{ "Session id:" } { &self.session_id }</p>
                 <button id="game_link_button" { onclick }>{ "Copy shareable link" }</button>
+                if self.game_ended {
+                    <button id="rematch_button" onclick={ rematch } disabled={ self.rematch_voted }>{ rematch_label }</button>
+                }
+                if self.is_host && self.game_ended {
+                    <button id="download_replay_button" onclick={ download_replay }>{ "Download replay" }</button>
+                }
+            </div>
+        }
+    }
+}
+
+impl GameComponent {
+    fn view_chat(&self, ctx: &Context<Self>) -> Html {
+        let send_chat = ctx.link().batch_callback(|event: KeyboardEvent| {
+            if event.key() == "Enter" {
+                Some(GameMsg::SendChat)
+            } else {
+                None
+            }
+        });
+        let toggle_chat_mode = ctx.link().callback(|_| GameMsg::ToggleChatMode);
+        html! {
+            <div id="chat">
+                { for self.chat.iter().map(|line| html! {
+                    <div>
+                        if line.team_only { { "[team] " } }
+                        { format!("{} #{} {}: ", if line.red { "Red" } else { "Blue" }, line.player_number, line.nickname) }
+                        { &line.text }
+                    </div>
+                }) }
+                <input id="chat-input" placeholder={ "Press Enter to send" } maxlength={ MAX_CHAT_MESSAGE_LENGTH.to_string() } onkeydown={ send_chat } />
+                <button id="chat_mode_button" onclick={ toggle_chat_mode }>{ if self.chat_team_only { "To team" } else { "To all" } }</button>
+            </div>
+        }
+    }
+
+    fn view_lobby(&self, ctx: &Context<Self>, lobby: &[LobbyPlayer]) -> Html {
+        let everyone_ready = lobby.iter().all(|player| player.ready);
+        html! {
+            <div id="lobby">
+                <p class="lead">{ "Waiting for the match to start. Players:" }</p>
+                <ul>
+                    { for lobby.iter().map(|player| html! {
+                        <li>
+                            { format!("{} #{} {}", if player.red { "Red" } else { "Blue" }, player.number, player.nickname) }
+                            if player.is_host { { " (host)" } }
+                            { if player.ready { " - ready" } else { " - not ready" } }
+                        </li>
+                    }) }
+                </ul>
+                if self.is_host {
+                    <button id="start_match_button" onclick={ ctx.link().callback(|_| GameMsg::StartMatch) } disabled={ !everyone_ready }>{ "Start match" }</button>
+                } else {
+                    <button id="ready_button" onclick={ ctx.link().callback(|_| GameMsg::ToggleReady) }>{ if self.ready { "Not ready" } else { "Ready" } }</button>
+                }
             </div>
         }
     }
 }
 
-fn init_game(canvas_node: NodeRef, is_host: bool, session_id: SessionId) -> FootballersGame {
+impl GameComponent {
+    /// Carries on with the match under a new host, possibly the local player.
+    fn migrate(&mut self, migration: Migration) {
+        self.session_id = migration.session_id.clone();
+        self.is_host = migration.become_host;
+        self.settings = migration.snapshot.settings;
+        self.game = Some(init_game(
+            self.canvas.clone(),
+            self.is_host,
+            self.session_id.clone(),
+            self.settings,
+            self.nickname.clone(),
+            self.interpolation_delay,
+            Some(migration),
+        ));
+    }
+}
+
+fn init_game(
+    canvas_node: NodeRef,
+    is_host: bool,
+    session_id: SessionId,
+    settings: MatchSettings,
+    nickname: String,
+    interpolation_delay: Option<f64>,
+    migration: Option<Migration>,
+) -> FootballersGame {
     let context = {
         let canvas = canvas_node
             .cast::<HtmlCanvasElement>()
@@ -158,12 +388,18 @@ fn init_game(canvas_node: NodeRef, is_host: bool, session_id: SessionId) -> Foot
             session_id,
             connection_type,
             signaling_server_url,
+            settings,
+            nickname,
+            migration,
         ))
     } else {
         FootballersGame::Client(ClientGame::new(
             session_id,
             connection_type,
             signaling_server_url,
+            nickname,
+            interpolation_delay,
+            migration,
         ))
     };
     game.init();
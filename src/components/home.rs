@@ -1,15 +1,22 @@
 use crate::components::game::GameQuery;
 use crate::components::{utils, Route};
+use crate::game::TieBreak;
 use wasm_peers::get_random_session_id;
 use yew::prelude::*;
 use yew_router::prelude::*;
 
 pub(crate) enum HomeMsg {
     UpdateInput,
+    UpdateNickname,
+    UpdateMatchLength,
+    UpdateTieBreak,
 }
 
 pub(crate) struct Home {
     input: String,
+    nickname: String,
+    match_minutes: Option<u32>,
+    tie_break: TieBreak,
 }
 
 impl Component for Home {
@@ -19,6 +26,9 @@ impl Component for Home {
     fn create(_ctx: &Context<Self>) -> Self {
         Self {
             input: String::new(),
+            nickname: String::new(),
+            match_minutes: None,
+            tie_break: TieBreak::default(),
         }
     }
 
@@ -28,29 +38,69 @@ impl Component for Home {
                 self.input = utils::get_input("join-input").value();
                 true
             }
+            Self::Message::UpdateNickname => {
+                self.nickname = utils::get_input("nickname-input").value();
+                true
+            }
+            Self::Message::UpdateMatchLength => {
+                self.match_minutes = utils::get_select("match-length").value().parse().ok();
+                if self.match_minutes.is_none() {
+                    // tie-break select is hidden for untimed matches
+                    self.tie_break = TieBreak::default();
+                }
+                true
+            }
+            Self::Message::UpdateTieBreak => {
+                self.tie_break = utils::get_select("tie-break")
+                    .value()
+                    .parse()
+                    .unwrap_or_default();
+                true
+            }
         }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let history = ctx.link().history().unwrap();
+        let nickname =
+            Some(self.nickname.trim().to_string()).filter(|nickname| !nickname.is_empty());
         let start_as_host = {
             let history = history.clone();
+            let match_minutes = self.match_minutes;
+            let tie_break = self.match_minutes.map(|_| self.tie_break);
+            let nickname = nickname.clone();
             Callback::once(move |_| {
                 history
                     .push_with_query(
                         Route::Game,
-                        GameQuery::new(get_random_session_id().into_inner(), true),
+                        GameQuery::new(
+                            get_random_session_id().into_inner(),
+                            true,
+                            match_minutes,
+                            tie_break,
+                            nickname,
+                        ),
                     )
                     .unwrap();
             })
         };
+        let update_match_length = ctx.link().callback(|_| HomeMsg::UpdateMatchLength);
+        let update_tie_break = ctx.link().callback(|_| HomeMsg::UpdateTieBreak);
+        let watch_replay = {
+            let history = history.clone();
+            Callback::once(move |_| history.push(Route::Replay))
+        };
         let update_input = ctx.link().callback(|_| HomeMsg::UpdateInput);
+        let update_nickname = ctx.link().callback(|_| HomeMsg::UpdateNickname);
         let join_existing = {
             let session_id = self.input.clone();
             Callback::once(move |_| {
                 if !session_id.is_empty() {
                     history
-                        .push_with_query(Route::Game, GameQuery::new(session_id, false))
+                        .push_with_query(
+                            Route::Game,
+                            GameQuery::new(session_id, false, None, None, nickname),
+                        )
                         .unwrap();
                 }
             })
@@ -68,9 +118,30 @@ impl Component for Home {
 
                         <p class="lead">{ "2D real-time multiplayer game in a browser." }</p>
                         <p class="lead">{ "Players divided in two teams play a football match on field with two goal posts." }</p>
-                        <p class="lead">{ "Goal of the game is for a team to score 3 points before the other team." }</p>
+                        <p class="lead">{ "Goal of the game is for a team to score 3 points before the other team, or to lead when the time runs out." }</p>
                         <p class="lead">{ "Use WASD to move, SPACE to shoot the ball." }</p>
                         <hr />
+                        <p class="lead">
+                            <input id="nickname-input"
+                                placeholder={ "Your nickname" }
+                                oninput={ update_nickname }
+                            />
+                        </p>
+                        <p class="lead">
+                            <select id="match-length" onchange={ update_match_length }>
+                                <option value="" selected=true>{ "No time limit" }</option>
+                                <option value="3">{ "3 minutes" }</option>
+                                <option value="5">{ "5 minutes" }</option>
+                            </select>
+                            if self.match_minutes.is_some() {
+                                <select id="tie-break" onchange={ update_tie_break }>
+                                    <option value="draw" selected=true>{ "Draw when level" }</option>
+                                    <option value="golden_goal">{ "Golden goal" }</option>
+                                    <option value="extra_time">{ "Extra time" }</option>
+                                    <option value="penalties">{ "Penalty shootout" }</option>
+                                </select>
+                            }
+                        </p>
                         <p class="lead">
                             <button onclick={ start_as_host } class="btn btn-lg btn-secondary fw-bold border-white bg-white">{ "Start game as host" }</button>
                         </p>
@@ -84,6 +155,10 @@ impl Component for Home {
                         <p class="lead">
                             <button onclick={ join_existing } class="btn btn-lg btn-secondary fw-bold border-white bg-white">{ "Join existing" }</button>
                         </p>
+                        <hr />
+                        <p class="lead">
+                            <button onclick={ watch_replay } class="btn btn-lg btn-secondary fw-bold border-white bg-white">{ "Watch a replay" }</button>
+                        </p>
                     </main>
                 </div>
         }
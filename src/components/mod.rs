@@ -1,9 +1,11 @@
 mod game;
 mod home;
+mod replay;
 mod utils;
 
 use crate::components::game::GameComponent;
 use crate::components::home::Home;
+use crate::components::replay::ReplayComponent;
 use yew::{html, Component, Context, Html};
 use yew_router::prelude::*;
 
@@ -13,6 +15,8 @@ enum Route {
     Home,
     #[at("/game")]
     Game,
+    #[at("/replay")]
+    Replay,
 }
 
 pub struct FootballersApp {}
@@ -48,5 +52,8 @@ fn switch(routes: &Route) -> Html {
         Route::Game => {
             html! { <GameComponent /> }
         }
+        Route::Replay => {
+            html! { <ReplayComponent /> }
+        }
     }
 }
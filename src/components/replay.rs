@@ -0,0 +1,198 @@
+use crate::game::{Replay, ReplayViewer, GAME_CANVAS_HEIGHT, GAME_CANVAS_WIDTH};
+use crate::utils::global_window;
+use gloo_file::callbacks::FileReader;
+use gloo_file::FileReadError;
+use log::error;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlCanvasElement, HtmlInputElement};
+use yew::prelude::*;
+
+const PLAYBACK_SPEEDS: [f64; 5] = [0.25, 0.5, 1.0, 2.0, 4.0];
+
+pub enum ReplayMsg {
+    LoadFile(Event),
+    FileLoaded(Result<String, FileReadError>),
+    Tick,
+    TogglePause,
+    Seek(InputEvent),
+    SetSpeed(f64),
+    StepForward,
+    StepBackward,
+}
+
+pub(crate) struct ReplayComponent {
+    canvas: NodeRef,
+    reader: Option<FileReader>,
+    viewer: Option<ReplayViewer>,
+    error: Option<String>,
+    tick_callback: Closure<dyn FnMut()>,
+}
+
+impl Component for ReplayComponent {
+    type Message = ReplayMsg;
+    type Properties = ();
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let tick_callback = {
+            let link = ctx.link().clone();
+            Closure::wrap(Box::new(move || link.send_message(ReplayMsg::Tick)) as Box<dyn FnMut()>)
+        };
+        Self {
+            canvas: NodeRef::default(),
+            reader: None,
+            viewer: None,
+            error: None,
+            tick_callback,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            ReplayMsg::LoadFile(event) => {
+                let input: HtmlInputElement = event.target_unchecked_into();
+                if let Some(file) = input.files().and_then(|files| files.get(0)) {
+                    let link = ctx.link().clone();
+                    self.reader = Some(gloo_file::callbacks::read_as_text(
+                        &gloo_file::File::from(file),
+                        move |result| link.send_message(ReplayMsg::FileLoaded(result)),
+                    ));
+                }
+                false
+            }
+            ReplayMsg::FileLoaded(result) => {
+                self.reader = None;
+                let replay = result
+                    .map_err(|error| format!("failed reading file: {error}"))
+                    .and_then(|json| Replay::from_json(&json));
+                match replay {
+                    Ok(replay) => {
+                        let start_ticking = self.viewer.is_none();
+                        let context = canvas_context(&self.canvas);
+                        self.viewer = Some(ReplayViewer::new(replay, context));
+                        self.error = None;
+                        if start_ticking {
+                            ctx.link().send_message(ReplayMsg::Tick);
+                        }
+                    }
+                    Err(error) => {
+                        error!("{error}");
+                        self.error = Some(error);
+                    }
+                }
+                true
+            }
+            ReplayMsg::Tick => match self.viewer.as_mut() {
+                Some(viewer) => {
+                    viewer.tick();
+                    if let Err(error) = global_window()
+                        .request_animation_frame(self.tick_callback.as_ref().unchecked_ref())
+                    {
+                        error!("Failed requesting next animation frame: {error:?}");
+                    }
+                    // playback position changes, so the timeline has to follow
+                    !viewer.paused()
+                }
+                None => false,
+            },
+            ReplayMsg::TogglePause => {
+                if let Some(viewer) = self.viewer.as_mut() {
+                    viewer.toggle_pause();
+                }
+                true
+            }
+            ReplayMsg::Seek(event) => {
+                let input: HtmlInputElement = event.target_unchecked_into();
+                if let (Some(viewer), Ok(tick)) = (self.viewer.as_mut(), input.value().parse()) {
+                    viewer.seek(tick);
+                }
+                true
+            }
+            ReplayMsg::SetSpeed(speed) => {
+                if let Some(viewer) = self.viewer.as_mut() {
+                    viewer.set_speed(speed);
+                }
+                true
+            }
+            ReplayMsg::StepForward => {
+                if let Some(viewer) = self.viewer.as_mut() {
+                    viewer.step_forward();
+                }
+                true
+            }
+            ReplayMsg::StepBackward => {
+                if let Some(viewer) = self.viewer.as_mut() {
+                    viewer.step_backward();
+                }
+                true
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let width = GAME_CANVAS_WIDTH.to_string();
+        let height = GAME_CANVAS_HEIGHT.to_string();
+        let load_file = ctx.link().callback(ReplayMsg::LoadFile);
+        html! {
+            <div class="px-3">
+                <canvas { width } { height } ref={ self.canvas.clone() }></canvas>
+                <p class="lead">
+                    <input type="file" accept=".json,application/json" onchange={ load_file } />
+                </p>
+                if let Some(error) = &self.error {
+                    <p class="lead">{ error }</p>
+                }
+                if let Some(viewer) = &self.viewer {
+                    { self.view_controls(ctx, viewer) }
+                }
+            </div>
+        }
+    }
+}
+
+impl ReplayComponent {
+    fn view_controls(&self, ctx: &Context<Self>, viewer: &ReplayViewer) -> Html {
+        let toggle_pause = ctx.link().callback(|_| ReplayMsg::TogglePause);
+        let step_backward = ctx.link().callback(|_| ReplayMsg::StepBackward);
+        let step_forward = ctx.link().callback(|_| ReplayMsg::StepForward);
+        let seek = ctx.link().callback(ReplayMsg::Seek);
+        html! {
+            <>
+                <p class="lead">
+                    <input type="range" class="w-100"
+                        min={ viewer.first_tick().to_string() }
+                        max={ viewer.last_tick().to_string() }
+                        value={ viewer.current_tick().to_string() }
+                        oninput={ seek }
+                    />
+                </p>
+                <p class="lead">
+                    <button onclick={ step_backward }>{ "<" }</button>
+                    <button onclick={ toggle_pause }>{ if viewer.paused() { "Play" } else { "Pause" } }</button>
+                    <button onclick={ step_forward }>{ ">" }</button>
+                </p>
+                <p class="lead">
+                    { for PLAYBACK_SPEEDS.iter().map(|&speed| {
+                        let onclick = ctx.link().callback(move |_| ReplayMsg::SetSpeed(speed));
+                        let class = if viewer.speed() == speed { "fw-bold" } else { "" };
+                        html! { <button { class } { onclick }>{ format!("{speed}x") }</button> }
+                    }) }
+                </p>
+            </>
+        }
+    }
+}
+
+fn canvas_context(canvas_node: &NodeRef) -> web_sys::CanvasRenderingContext2d {
+    let context = canvas_node
+        .cast::<HtmlCanvasElement>()
+        .expect("no canvas element on page yet")
+        .get_context("2d")
+        .unwrap()
+        .unwrap()
+        .dyn_into::<web_sys::CanvasRenderingContext2d>()
+        .unwrap();
+    context.set_text_align("center");
+    context.set_text_baseline("middle");
+    context
+}
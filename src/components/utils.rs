@@ -1,6 +1,6 @@
 use crate::utils::global_window;
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlInputElement, UrlSearchParams};
+use web_sys::{HtmlInputElement, HtmlSelectElement, UrlSearchParams};
 
 pub fn get_query_params() -> UrlSearchParams {
     let search = global_window().location().search().unwrap();
@@ -16,3 +16,13 @@ pub fn get_input(id: &str) -> HtmlInputElement {
         .dyn_into::<HtmlInputElement>()
         .expect("element is not an input")
 }
+
+pub fn get_select(id: &str) -> HtmlSelectElement {
+    global_window()
+        .document()
+        .expect("document node is missing")
+        .get_element_by_id(id)
+        .expect("could not find select element by id")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("element is not a select")
+}
@@ -1,12 +1,25 @@
 use crate::game::constants::{
-    BALL_RADIUS, GOAL_BREADTH, PITCH_BOTTOM_LINE, PITCH_LEFT_LINE, PITCH_LINE_WIDTH,
-    PITCH_RIGHT_LINE, PITCH_TOP_LINE, RESET_TIME, STADIUM_HEIGHT, STADIUM_WIDTH,
+    BALL_RADIUS, FIXED_TIMESTEP, GOAL_BREADTH, HOST_TIMEOUT, INPUT_REDUNDANCY,
+    INTERPOLATION_CATCH_UP, INTERPOLATION_DELAY, MAX_STEPS_PER_TICK, NOTICE_TIME,
+    PITCH_BOTTOM_LINE, PITCH_LEFT_LINE, PITCH_LINE_WIDTH, PITCH_RIGHT_LINE, PITCH_TOP_LINE,
+    RESET_TIME, STADIUM_HEIGHT, STADIUM_WIDTH, STATE_HISTORY, TICKS_PER_SECOND,
 };
+use crate::game::delta::{NetworkState, StateDelta};
 use crate::game::input::PlayerInput;
-use crate::game::utils::{Circle, Edge, Message, Score};
-use crate::game::{input, rendering, Game};
-use crate::utils::global_window;
-use std::cell::RefCell;
+use crate::game::latency::Ping;
+use crate::game::migration::{Migration, MigrationSnapshot};
+use crate::game::prediction::Prediction;
+use crate::game::protocol::Handshake;
+use crate::game::shootout::PenaltyStatus;
+use crate::game::utils::{
+    ChatLine, Circle, ClientMessage, Edge, LobbyPlayer, Message, PlayerBody, Score,
+};
+use crate::game::{input, protocol, rendering, Game};
+use crate::utils::{global_window, now_seconds, reconnect_token};
+use log::{error, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt;
 use std::rc::Rc;
 use wasm_bindgen::JsCast;
 use wasm_peers::one_to_many::MiniClient;
@@ -22,12 +35,18 @@ impl ClientGame {
         session_id: SessionId,
         connection_type: ConnectionType,
         signaling_server_url: &str,
+        nickname: String,
+        interpolation_delay: Option<f64>,
+        migration: Option<Migration>,
     ) -> Self {
         ClientGame {
             inner: Rc::new(RefCell::new(ClientGameInner::new(
                 session_id,
                 connection_type,
                 signaling_server_url,
+                nickname,
+                interpolation_delay.unwrap_or(INTERPOLATION_DELAY),
+                migration,
             ))),
         }
     }
@@ -35,11 +54,28 @@ impl ClientGame {
 
 impl Game for ClientGame {
     fn init(&mut self) {
-        let on_open_callback = || {};
+        let inner = self.inner.clone();
+        let on_open_callback = move || {
+            let handshake = Handshake::local().encode();
+            if let Err(error) = inner.borrow().mini_client.send_message_to_host(&handshake) {
+                error!("failed to shake hands with host: {error:?}");
+            }
+        };
 
         let inner = self.inner.clone();
         let on_message_callback = move |message: String| {
-            let message = serde_json::from_str::<Message>(&message).unwrap();
+            inner.borrow_mut().last_heard_from_host = now_seconds();
+            if !inner.borrow().host_compatible {
+                inner.borrow_mut().shake_hands(&message);
+                return;
+            }
+            let message = match protocol::decode::<Message>(&message) {
+                Ok(message) => message,
+                Err(error) => {
+                    inner.borrow_mut().message_error(&error);
+                    return;
+                }
+            };
 
             match message {
                 Message::GameInit {
@@ -47,25 +83,98 @@ impl Game for ClientGame {
                     goal_posts,
                     players,
                     ball,
+                    score,
+                    game_ended,
+                    game_started,
                 } => {
                     inner.borrow_mut().edges = edges;
                     inner.borrow_mut().goal_posts = goal_posts;
                     inner.borrow_mut().players = players;
                     inner.borrow_mut().ball = ball;
+                    inner.borrow_mut().score = score;
+                    inner.borrow_mut().game_ended = game_ended;
+                    let mut inner = inner.borrow_mut();
+                    if game_started {
+                        inner.lobby = None;
+                    } else if inner.lobby.is_none() {
+                        inner.lobby = Some(Vec::new());
+                    }
                 }
-                Message::GameState { players, ball } => {
-                    inner.borrow_mut().players = players;
-                    inner.borrow_mut().ball = ball;
+                // only replays hold full game states, players get keyframes and deltas
+                Message::GameState { .. } => {}
+                Message::Keyframe {
+                    state,
+                    player_body,
+                    input_ack,
+                } => {
+                    inner.borrow_mut().awaiting_keyframe = false;
+                    inner.borrow_mut().receive_state(state);
+                    inner.borrow_mut().reconcile(player_body, input_ack);
+                }
+                Message::StateDelta {
+                    delta,
+                    player_body,
+                    input_ack,
+                } => {
+                    inner.borrow_mut().receive_delta(delta);
+                    inner.borrow_mut().reconcile(player_body, input_ack);
                 }
                 Message::GoalScored { score, red_scored } => {
                     inner.borrow_mut().score = score;
-                    inner.borrow_mut().red_scored = red_scored;
-                    inner.borrow_mut().blue_scored = !red_scored;
-                    inner.borrow_mut().timer = RESET_TIME;
+                    let tick = inner.borrow().host_tick();
+                    inner.borrow_mut().last_goal = Some((tick, red_scored));
                 }
                 Message::GameEnded => {
                     inner.borrow_mut().game_ended = true;
                 }
+                Message::PenaltyKick {
+                    red_kicking,
+                    shooter_number,
+                    round,
+                } => {
+                    let status = PenaltyStatus::next_kick(
+                        inner.borrow().penalty_status.as_ref(),
+                        red_kicking,
+                        shooter_number,
+                        round,
+                    );
+                    inner.borrow_mut().penalty_status = Some(status);
+                }
+                Message::PenaltyTaken {
+                    red_kicking,
+                    scored,
+                    penalties,
+                } => {
+                    if let Some(status) = inner.borrow_mut().penalty_status.as_mut() {
+                        status.kick_taken(red_kicking, scored, penalties);
+                    }
+                }
+                Message::PlayerLeft { red, player_number } => {
+                    let tick = inner.borrow().host_tick();
+                    inner.borrow_mut().departure = Some((tick, red, player_number));
+                }
+                Message::RematchVotes { votes, players } => {
+                    inner.borrow_mut().rematch_votes = (votes, players);
+                }
+                Message::Rematch => inner.borrow_mut().rematch(),
+                Message::Lobby { players } => {
+                    inner.borrow_mut().lobby = Some(players);
+                }
+                Message::MatchStarted => {
+                    inner.borrow_mut().lobby = None;
+                }
+                Message::Chat { line } => {
+                    inner.borrow_mut().chat_lines.push(line);
+                }
+                Message::Ping { sent_at } => {
+                    let pong = protocol::encode(&ClientMessage::Pong { sent_at });
+                    // allow some messages to fail, the next ping comes soon
+                    let _ = inner.borrow().mini_client.send_message_to_host(&pong);
+                }
+                Message::Migration { snapshot } => {
+                    inner.borrow_mut().migration_snapshot = Some(snapshot);
+                }
+                Message::Dropped => inner.borrow_mut().rejoin(),
             }
         };
 
@@ -83,21 +192,109 @@ impl Game for ClientGame {
     fn ended(&self) -> bool {
         self.inner.borrow().game_ended
     }
+
+    fn rematch(&mut self) {
+        let vote = protocol::encode(&ClientMessage::RematchVote);
+        if let Err(error) = self.inner.borrow().mini_client.send_message_to_host(&vote) {
+            error!("failed to vote for rematch: {error:?}");
+        }
+    }
+
+    fn lobby(&self) -> Option<Vec<LobbyPlayer>> {
+        self.inner.borrow().lobby.clone()
+    }
+
+    fn set_ready(&mut self, ready: bool) {
+        let message = protocol::encode(&ClientMessage::Ready { ready });
+        if let Err(error) = self
+            .inner
+            .borrow()
+            .mini_client
+            .send_message_to_host(&message)
+        {
+            error!("failed to tell host about being ready: {error:?}");
+        }
+    }
+
+    fn send_chat(&mut self, text: &str, team_only: bool) {
+        let message = ClientMessage::Chat {
+            text: text.to_string(),
+            team_only,
+        };
+        let message = protocol::encode(&message);
+        if let Err(error) = self
+            .inner
+            .borrow()
+            .mini_client
+            .send_message_to_host(&message)
+        {
+            error!("failed to send chat message: {error:?}");
+        }
+    }
+
+    fn take_chat(&mut self) -> Vec<ChatLine> {
+        std::mem::take(&mut self.inner.borrow_mut().chat_lines)
+    }
+
+    fn take_migration(&mut self) -> Option<Migration> {
+        self.inner.borrow_mut().pending_migration.take()
+    }
+
+    fn incompatible_host(&self) -> Option<String> {
+        self.inner.borrow().incompatible_host.clone()
+    }
 }
 
 struct ClientGameInner {
     mini_client: MiniClient,
+    nickname: String,
+    // whether the host answered the handshake with a compatible version, and its version if not
+    host_compatible: bool,
+    incompatible_host: Option<String>,
+    // messages from the host that couldn't be read
+    message_errors: u32,
     edges: Vec<Edge>,
     goal_posts: Vec<Circle>,
     players: Vec<Circle>,
     ball: Circle,
     score: Score,
-    red_scored: bool,
-    blue_scored: bool,
+    // host tick of the last goal and whether it was red who scored
+    last_goal: Option<(u32, bool)>,
     game_ended: bool,
-    timer: u32,
+    time_left: Option<u32>,
+    overtime: bool,
+    // game states received from the host, newest last, kept as bases for deltas
+    // and to interpolate between
+    states: VecDeque<NetworkState>,
+    awaiting_keyframe: bool,
+    // seconds the match is drawn behind the newest state, and the fractional tick being drawn
+    interpolation_delay: f64,
+    render_tick: Option<f64>,
+    last_frame_time: Option<f64>,
+    // local player's own body, moved ahead of the host by inputs sent once per simulation step
+    prediction: Prediction,
+    input_sequence: u32,
+    // latest inputs, oldest first, all of them go in every input message
+    recent_inputs: VecDeque<PlayerInput>,
+    accumulator: f64,
+    penalty_status: Option<PenaltyStatus>,
+    // host tick when a player left, with their team and number
+    departure: Option<(u32, bool, i32)>,
+    rematch_votes: (u32, u32),
+    lobby: Option<Vec<LobbyPlayer>>,
+    // chat messages not yet shown
+    chat_lines: Vec<ChatLine>,
+    // latest snapshot of the match from the host, to carry on with if it goes silent
+    migration_snapshot: Option<MigrationSnapshot>,
+    last_heard_from_host: f64,
+    // local player's id as told by the current host, and by the previous one after a migration
+    player_id: Option<u32>,
+    previous_player_id: Option<u32>,
+    // switch to a new host, waiting to be picked up
+    pending_migration: Option<Migration>,
     context: CanvasRenderingContext2d,
     player_input: Rc<RefCell<PlayerInput>>,
+    match_running: Rc<Cell<bool>>,
 }
 
 impl ClientGameInner {
@@ -105,6 +302,9 @@ impl ClientGameInner {
         session_id: SessionId,
         connection_type: ConnectionType,
         signaling_server_url: &str,
+        nickname: String,
+        interpolation_delay: f64,
+        migration: Option<Migration>,
     ) -> Self {
         let mini_client = MiniClient::new(signaling_server_url, session_id, connection_type)
             .expect("failed to create network manager");
@@ -127,36 +327,317 @@ impl ClientGameInner {
         context.set_text_align("center");
         context.set_text_baseline("middle");
 
+        // after a migration the match carries on as it was, until the new host says otherwise
+        // and without one it starts in the lobby, until the host says the match is under way
+        let (score, game_ended, time_left, overtime, game_started) = match migration.as_ref() {
+            Some(migration) => (
+                migration.snapshot.score.clone(),
+                migration.snapshot.game_ended,
+                migration.snapshot.time_left,
+                migration.snapshot.overtime,
+                migration.snapshot.game_started,
+            ),
+            None => (Score::new(0, 0), false, None, false, false),
+        };
+        let (migration_snapshot, previous_player_id) = match migration {
+            Some(migration) => (Some(migration.snapshot), migration.player_id),
+            None => (None, None),
+        };
+        let match_running = Rc::new(Cell::new(false));
+
         ClientGameInner {
             mini_client,
+            nickname,
+            host_compatible: false,
+            incompatible_host: None,
+            message_errors: 0,
             edges: Vec::new(),
             goal_posts: Vec::new(),
             players: Vec::new(),
             ball: Circle::new(0.0, 0.0, BALL_RADIUS, false, -1),
-            score: Score::new(0, 0),
-            red_scored: false,
-            blue_scored: false,
-            game_ended: false,
-            timer: 0,
+            score,
+            last_goal: None,
+            game_ended,
+            time_left,
+            overtime,
+            states: VecDeque::new(),
+            awaiting_keyframe: false,
+            interpolation_delay,
+            render_tick: None,
+            last_frame_time: None,
+            prediction: Prediction::new(),
+            input_sequence: 0,
+            recent_inputs: VecDeque::new(),
+            accumulator: 0.0,
+            penalty_status: None,
+            departure: None,
+            rematch_votes: (0, 0),
+            lobby: (!game_started).then(Vec::new),
+            chat_lines: Vec::new(),
+            migration_snapshot,
+            last_heard_from_host: now_seconds(),
+            player_id: None,
+            previous_player_id,
+            pending_migration: None,
             context,
-            player_input: input::local_player_input(),
+            player_input: input::local_player_input(match_running.clone()),
+            match_running,
         }
     }
 
-    fn tick(&mut self) {
-        if self.timer == 0 {
-            self.red_scored = false;
-            self.blue_scored = false;
-        } else {
-            self.timer -= 1;
+    fn rematch(&mut self) {
+        self.score = Score::new(0, 0);
+        self.last_goal = None;
+        self.game_ended = false;
+        self.time_left = None;
+        self.overtime = false;
+        self.penalty_status = None;
+        self.departure = None;
+        self.rematch_votes = (0, 0);
+    }
+
+    /// Checks the host's answer to the handshake and introduces the local player if they're
+    /// compatible. Until then anything else the host sends to everyone is skipped.
+    fn shake_hands(&mut self, message: &str) {
+        let handshake = match Handshake::decode(message) {
+            Ok(handshake) => handshake,
+            Err(error) => {
+                self.message_error(&error);
+                return;
+            }
+        };
+        if !handshake.compatible() {
+            error!(
+                "host runs version {}, which is incompatible with the local {}",
+                handshake.version(),
+                Handshake::local().version()
+            );
+            self.incompatible_host = Some(handshake.version());
+            return;
+        }
+        self.host_compatible = true;
+        let hello = ClientMessage::Hello {
+            reconnect_token: reconnect_token(),
+            nickname: self.nickname.clone(),
+            player_id: self.previous_player_id,
+        };
+        let hello = protocol::encode(&hello);
+        if let Err(error) = self.mini_client.send_message_to_host(&hello) {
+            error!("failed to introduce to host: {error:?}");
         }
+    }
 
-        // on each frame, send input to host
-        let message = serde_json::to_string::<PlayerInput>(&self.player_input.borrow()).unwrap();
+    /// Shakes hands with the host again after it dropped the local player,
+    /// who gets introduced again once the host answers.
+    fn rejoin(&mut self) {
+        warn!("host dropped the local player, rejoining");
+        self.host_compatible = false;
+        let handshake = Handshake::local().encode();
+        if let Err(error) = self.mini_client.send_message_to_host(&handshake) {
+            error!("failed to shake hands with host: {error:?}");
+        }
+    }
 
+    fn message_error(&mut self, error: &dyn fmt::Display) {
+        self.message_errors += 1;
+        error!(
+            "malformed message from host ({} so far): {error}",
+            self.message_errors
+        );
+    }
+
+    fn receive_state(&mut self, state: NetworkState) {
+        self.time_left = state.time_left;
+        self.overtime = state.overtime;
+        self.states.push_back(state);
+        if self.states.len() > STATE_HISTORY {
+            self.states.pop_front();
+        }
+    }
+
+    fn receive_delta(&mut self, delta: StateDelta) {
+        // a delta not newer than the current state has been overtaken by others
+        let newest = self.states.back().map(|state| state.sequence);
+        if newest.is_some_and(|newest| newest >= delta.sequence) {
+            return;
+        }
+        let state = self
+            .states
+            .iter()
+            .find(|state| state.sequence == delta.base_sequence)
+            .map(|base| base.apply(&delta));
+        match state {
+            Some(state) => self.receive_state(state),
+            None => self.request_keyframe(),
+        }
+    }
+
+    fn reconcile(&mut self, player_body: Option<PlayerBody>, input_ack: Option<u32>) {
+        self.player_id = player_body.map(|player_body| player_body.id);
+        let newest = self.states.back();
+        // red like the body starts out, until the host's state says otherwise
+        let red = newest
+            .zip(self.player_id)
+            .and_then(|(state, id)| state.players.get(&id))
+            .is_none_or(|player| player.red);
+        let kickoff = newest.and_then(|state| state.kickoff);
+        self.prediction
+            .reconcile(player_body.as_ref(), input_ack, red, kickoff);
+    }
+
+    /// Tick of the newest state received from the host.
+    fn host_tick(&self) -> u32 {
+        self.states.back().map_or(0, |state| state.tick)
+    }
+
+    /// Whether the local player moves freely, so it's worth predicting where to.
+    /// In the lobby and during a penalty shootout the host holds players in place.
+    fn predicting(&self) -> bool {
+        self.lobby.is_none() && !self.game_ended && self.penalty_status.is_none()
+    }
+
+    /// Asks the host for a full game state, after a delta against an unknown state arrived.
+    fn request_keyframe(&mut self) {
+        if self.awaiting_keyframe {
+            return;
+        }
+        self.awaiting_keyframe = true;
+        let message = protocol::encode(&ClientMessage::KeyframeRequest);
+        if let Err(error) = self.mini_client.send_message_to_host(&message) {
+            error!("failed to request game state: {error:?}");
+            self.awaiting_keyframe = false;
+        }
+    }
+
+    /// Moves players and the ball to where they were `interpolation_delay` behind
+    /// the newest state, between the two received states around that moment.
+    fn interpolate(&mut self, elapsed: f64) {
+        let newest_tick = match self.states.back() {
+            Some(newest) => newest.tick as f64,
+            None => return,
+        };
+        let ticks_per_second = TICKS_PER_SECOND as f64;
+        let target = newest_tick - self.interpolation_delay * ticks_per_second;
+        let render_tick = match self.render_tick {
+            // after a long pause, e.g. with the tab in background, jump straight to the target
+            Some(render_tick) if (target - render_tick).abs() < ticks_per_second => {
+                let render_tick = render_tick + elapsed * ticks_per_second;
+                render_tick + (target - render_tick) * INTERPOLATION_CATCH_UP
+            }
+            _ => target,
+        }
+        .min(newest_tick);
+        self.render_tick = Some(render_tick);
+
+        let first_after = self
+            .states
+            .iter()
+            .position(|state| state.tick as f64 >= render_tick)
+            .unwrap_or(self.states.len() - 1);
+        // of the states taken at the same tick, like lobby updates, the last one counts
+        let after_tick = self.states[first_after].tick;
+        let last_after = self
+            .states
+            .iter()
+            .rposition(|state| state.tick == after_tick)
+            .unwrap_or(first_after);
+        let after = &self.states[last_after];
+        let before = first_after
+            .checked_sub(1)
+            .map(|before| &self.states[before])
+            .filter(|before| before.tick < after_tick);
+        let mut state = match before {
+            Some(before) => {
+                let fraction =
+                    (render_tick - before.tick as f64) / (after_tick - before.tick) as f64;
+                before.interpolate(after, fraction.clamp(0.0, 1.0) as f32)
+            }
+            None => after.clone(),
+        };
+        // local player is drawn where they are predicted to be by now
+        if let Some((id, x, y)) = self.prediction.position().filter(|_| self.predicting()) {
+            if let Some(player) = state.players.get_mut(&id) {
+                player.x = x;
+                player.y = y;
+            }
+        }
+        self.players = state.players();
+        self.ball = state.ball;
+    }
+
+    /// Sends the current input to the host, along with a few previous ones in case
+    /// they got lost, predicting its effect on the local player.
+    fn send_input(&mut self) {
+        if !self.host_compatible {
+            return;
+        }
+        self.input_sequence += 1;
+        let input = *self.player_input.borrow();
+        self.recent_inputs.push_back(input);
+        if self.recent_inputs.len() > INPUT_REDUNDANCY {
+            self.recent_inputs.pop_front();
+        }
+        // until a keyframe arrives, there's no state worth acknowledging
+        let ack = if self.awaiting_keyframe {
+            None
+        } else {
+            self.states.back().map(|state| state.sequence)
+        };
+        let message = protocol::encode(&ClientMessage::Input {
+            inputs: self.recent_inputs.iter().copied().collect(),
+            sequence: self.input_sequence,
+            ack,
+        });
         // allow some messages to fail
         let _ = self.mini_client.send_message_to_host(&message);
 
+        if self.predicting() {
+            self.prediction.step(self.input_sequence, input);
+        }
+    }
+
+    /// Hands the match over to the first successor in the latest snapshot, once the host
+    /// has been silent for too long. That successor is crossed out of the snapshot, so should
+    /// they not show up as the new host either, the next one takes over.
+    fn check_host_timeout(&mut self, now: f64) {
+        if self.pending_migration.is_some() || now - self.last_heard_from_host <= HOST_TIMEOUT {
+            return;
+        }
+        let mut snapshot = match self.migration_snapshot.take() {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        if snapshot.succession.is_empty() {
+            return;
+        }
+        let successor = snapshot.succession.remove(0);
+        // players keep their ids across hosts, even if the current one never told it
+        let player_id = self.player_id.or(self.previous_player_id);
+        self.pending_migration = Some(Migration {
+            session_id: snapshot.successor_session_id(successor),
+            become_host: player_id == Some(successor),
+            player_id,
+            snapshot,
+        });
+    }
+
+    fn tick(&mut self) {
+        let now = now_seconds();
+        let elapsed = self.last_frame_time.map_or(0.0, |last| now - last);
+        self.last_frame_time = Some(now);
+        self.check_host_timeout(now);
+        self.match_running
+            .set(self.lobby.is_none() && !self.game_ended);
+
+        // inputs go out at the host's pace, whatever the display refresh rate
+        let max_accumulated = MAX_STEPS_PER_TICK as f64 * FIXED_TIMESTEP as f64;
+        self.accumulator = (self.accumulator + elapsed).min(max_accumulated);
+        while self.accumulator >= FIXED_TIMESTEP as f64 {
+            self.accumulator -= FIXED_TIMESTEP as f64;
+            self.send_input();
+        }
+        self.interpolate(elapsed);
+
         self.draw();
     }
 
@@ -181,18 +662,86 @@ impl ClientGameInner {
             STADIUM_WIDTH as f64,
             PITCH_TOP_LINE as f64,
         );
+        if self.time_left.is_some() || self.overtime {
+            rendering::draw_clock(
+                &self.context,
+                self.time_left.map(|time_left| time_left / TICKS_PER_SECOND),
+                self.overtime,
+                STADIUM_WIDTH as f64,
+                PITCH_TOP_LINE as f64,
+            );
+        }
         rendering::draw_players(&self.context, &self.players);
         rendering::draw_ball(&self.context, &self.ball);
-        if self.red_scored {
-            rendering::draw_red_scored(&self.context, STADIUM_WIDTH as f64, STADIUM_HEIGHT as f64);
+        // notices last a number of host ticks, whatever the display refresh rate
+        let host_tick = self.host_tick();
+        if let Some((goal_tick, red_scored)) = self.last_goal {
+            if host_tick < goal_tick.saturating_add(RESET_TIME) {
+                if red_scored {
+                    rendering::draw_red_scored(
+                        &self.context,
+                        STADIUM_WIDTH as f64,
+                        STADIUM_HEIGHT as f64,
+                    );
+                } else {
+                    rendering::draw_blue_scored(
+                        &self.context,
+                        STADIUM_WIDTH as f64,
+                        STADIUM_HEIGHT as f64,
+                    );
+                }
+            }
         }
-        if self.blue_scored {
-            rendering::draw_blue_scored(&self.context, STADIUM_WIDTH as f64, STADIUM_HEIGHT as f64);
+        if let Some((tick, red, player_number)) = self.departure {
+            if host_tick < tick.saturating_add(NOTICE_TIME) {
+                rendering::draw_player_left(
+                    &self.context,
+                    red,
+                    player_number,
+                    STADIUM_WIDTH as f64,
+                    PITCH_TOP_LINE as f64,
+                );
+            }
+        }
+        if let Some(status) = self.penalty_status.as_ref() {
+            rendering::draw_penalty_status(
+                &self.context,
+                status,
+                STADIUM_WIDTH as f64,
+                STADIUM_HEIGHT as f64,
+                PITCH_BOTTOM_LINE as f64,
+            );
         }
         if self.game_ended {
             rendering::draw_game_ended(
                 &self.context,
                 &self.score,
+                self.overtime,
+                self.penalty_status.as_ref().map(|status| &status.penalties),
+                STADIUM_WIDTH as f64,
+                STADIUM_HEIGHT as f64,
+            );
+            rendering::draw_rematch_votes(
+                &self.context,
+                self.rematch_votes.0,
+                self.rematch_votes.1,
+                STADIUM_WIDTH as f64,
+                STADIUM_HEIGHT as f64,
+            );
+        }
+        if let Some(state) = self
+            .states
+            .back()
+            .filter(|_| self.player_input.borrow().player_list)
+        {
+            let players: Vec<(&Circle, Option<Ping>)> = state
+                .players
+                .iter()
+                .map(|(id, player)| (player, state.pings.get(id).copied()))
+                .collect();
+            rendering::draw_player_list(
+                &self.context,
+                &players,
                 STADIUM_WIDTH as f64,
                 STADIUM_HEIGHT as f64,
             );
@@ -2,8 +2,68 @@ pub const PLAYER_ACCELERATION: f32 = 2_000.0;
 pub const PLAYER_TOP_SPEED: f32 = 90.0;
 pub const BALL_TOP_SPEED: f32 = 200.0;
 
-pub const RESET_TIME: u32 = 60 * 3;
+pub const TICKS_PER_SECOND: u32 = 60;
+pub const FIXED_TIMESTEP: f32 = 1.0 / TICKS_PER_SECOND as f32;
+pub const MAX_STEPS_PER_TICK: u32 = 5;
+
+pub const RESET_TIME: u32 = TICKS_PER_SECOND * 3;
+/// How long notices, like a player leaving, stay on screen.
+pub const NOTICE_TIME: u32 = TICKS_PER_SECOND * 3;
+/// Seconds without any message from a player after which the host drops them.
+pub const PLAYER_TIMEOUT: f64 = 10.0;
+/// Seconds after leaving during which a player rejoining gets their old team and number back.
+pub const RECONNECT_GRACE: f64 = 60.0;
+/// A connection silent for this many seconds may be taken over by a reconnecting player,
+/// as it's most likely dead but not yet timed out. A tab in the background only answers pings,
+/// so this has to be a few ping intervals.
+pub const STALE_CONNECTION_TIME: f64 = 4.0 * PING_INTERVAL;
+/// Number of recent game states kept by the host and players, as bases for state deltas.
+pub const STATE_HISTORY: usize = TICKS_PER_SECOND as usize;
+/// Seconds between the snapshots the host sends for players to carry on with if it leaves.
+pub const MIGRATION_SNAPSHOT_INTERVAL: f64 = 1.0;
+/// Seconds between the host's pings and migration snapshot checks when its tab is in the
+/// background and ticks stop. Browsers still run timers there, about once a second.
+pub const HEARTBEAT_INTERVAL: f64 = 0.5;
+/// Seconds without any message from the host after which players hand the match over
+/// to a successor among them.
+pub const HOST_TIMEOUT: f64 = 5.0;
+/// Malformed messages the host takes from a player before disconnecting them.
+pub const MAX_MESSAGE_ERRORS: u32 = 20;
+/// Seconds between pings the host sends to measure each player's latency.
+pub const PING_INTERVAL: f64 = 1.0;
+/// Number of latest inputs repeated in every input message, so a few lost messages lose no input.
+pub const INPUT_REDUNDANCY: usize = 5;
+/// Input messages the host accepts from a player per second, the rest are dropped.
+/// Players send one per simulation step, so this leaves room for bursts after a slow frame.
+pub const MAX_INPUT_RATE: u32 = 2 * TICKS_PER_SECOND;
+/// Chat messages the host accepts from a player per second, everyone gets each one relayed.
+pub const MAX_CHAT_RATE: u32 = 2;
+/// Other messages the host accepts from a player per second, like pongs or rematch votes.
+pub const MAX_MESSAGE_RATE: u32 = 10;
+/// Seconds in which a player may go over any of the message rates before being disconnected.
+pub const MAX_RATE_VIOLATIONS: u32 = 5;
+/// Inputs the host holds for a player beyond this many are dropped, oldest first,
+/// so a player whose clock runs slightly fast doesn't build up lag.
+pub const MAX_QUEUED_INPUTS: usize = 6;
+/// Seconds players see the match behind the newest state from the host,
+/// so there are states on both sides of what's drawn to interpolate between.
+pub const INTERPOLATION_DELAY: f64 = 0.1;
+/// Fraction of the distance to the target render tick covered each frame,
+/// it keeps the pace of the match steady while following the host's clock.
+pub const INTERPOLATION_CATCH_UP: f64 = 0.05;
 pub const MAX_GOALS: u32 = 3;
+/// Length of overtime in seconds, when the tie-break is extra time.
+pub const EXTRA_TIME: u32 = 2 * 60;
+
+pub const PENALTY_ROUNDS: u32 = 5;
+pub const PENALTY_SPOT_DISTANCE: f32 = 90.0;
+pub const PENALTY_KICK_TIME: u32 = TICKS_PER_SECOND * 5;
+pub const PENALTY_RESULT_TIME: u32 = TICKS_PER_SECOND * 2;
+
+pub const MAX_NICKNAME_LENGTH: usize = 16;
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 200;
+/// Longest timed match a host can set up, longer ones are played without a time limit.
+pub const MAX_MATCH_MINUTES: u32 = 90;
 
 pub const PLAYER_DIAMETER: f32 = 30.0;
 pub const PLAYER_RADIUS: f32 = PLAYER_DIAMETER / 2.0;
@@ -24,9 +84,14 @@ pub const PITCH_TOP_LINE: f32 = 0.0 + PLAYER_DIAMETER;
 pub const PITCH_BOTTOM_LINE: f32 = PITCH_TOP_LINE + PITCH_HEIGHT;
 pub const STADIUM_WIDTH: f32 = 2.0 * PLAYER_DIAMETER + PITCH_WIDTH + 2.0 * PLAYER_DIAMETER;
 pub const STADIUM_HEIGHT: f32 = 2.0 * PLAYER_DIAMETER + PITCH_HEIGHT;
+pub const CENTRE_CIRCLE_RADIUS: f32 = STADIUM_HEIGHT / 6.0;
+/// How far the ball must move from the centre spot for the kickoff to be taken.
+pub const KICKOFF_BALL_DISTANCE: f32 = 1.0;
 
 pub const PITCH_LINES_GROUP: u32 = 0b_0000_0001;
 pub const GOAL_POSTS_GROUP: u32 = 0b_0000_0010;
 pub const PLAYERS_GROUP: u32 = 0b_0000_0100;
 pub const STADIUM_WALLS_GROUP: u32 = 0b_0000_1000;
 pub const BALL_GROUP: u32 = 0b_0001_0000;
+pub const RED_KICKOFF_GROUP: u32 = 0b_0010_0000;
+pub const BLUE_KICKOFF_GROUP: u32 = 0b_0100_0000;
@@ -0,0 +1,286 @@
+use crate::game::latency::Ping;
+use crate::game::simulation::Snapshot;
+use crate::game::utils::Circle;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Game state as sent to players, who acknowledge it by its `sequence`
+/// so the next one can be sent as a `StateDelta` against it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NetworkState {
+    /// Number of the state among those sent by the host, it grows even while the clock is stopped.
+    pub sequence: u32,
+    /// Simulation tick the state was taken at.
+    pub tick: u32,
+    /// Players keyed by their stable id.
+    pub players: BTreeMap<u32, Circle>,
+    pub ball: Circle,
+    pub time_left: Option<u32>,
+    pub overtime: bool,
+    /// Team taking the kickoff in progress, true for red.
+    pub kickoff: Option<bool>,
+    /// Ping of each player the host has measured, by player id.
+    pub pings: BTreeMap<u32, Ping>,
+}
+
+/// Difference between two game states, only what changed is included.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StateDelta {
+    /// State the delta applies to.
+    pub base_sequence: u32,
+    pub sequence: u32,
+    pub tick: u32,
+    /// Players whose only change is their position.
+    pub moved: Vec<(u32, f32, f32)>,
+    /// Players who joined or whose team, number or nickname changed, sent in full.
+    pub changed: Vec<(u32, Circle)>,
+    pub removed: Vec<u32>,
+    pub ball: Option<(f32, f32)>,
+    pub time_left: Option<u32>,
+    pub overtime: bool,
+    pub kickoff: Option<bool>,
+    /// All the pings, if any of them changed.
+    pub pings: Option<BTreeMap<u32, Ping>>,
+}
+
+impl NetworkState {
+    pub fn from_snapshot(
+        sequence: u32,
+        snapshot: &Snapshot,
+        pings: BTreeMap<u32, Ping>,
+    ) -> NetworkState {
+        NetworkState {
+            sequence,
+            tick: snapshot.tick,
+            players: snapshot
+                .player_ids
+                .iter()
+                .copied()
+                .zip(snapshot.players.iter().cloned())
+                .collect(),
+            ball: snapshot.ball.clone(),
+            time_left: snapshot.time_left,
+            overtime: snapshot.overtime,
+            kickoff: snapshot.kickoff,
+            pings,
+        }
+    }
+
+    pub fn players(&self) -> Vec<Circle> {
+        self.players.values().cloned().collect()
+    }
+
+    /// What has to be sent to a player holding `base` for them to arrive at this state.
+    pub fn delta_from(&self, base: &NetworkState) -> StateDelta {
+        let mut moved = Vec::new();
+        let mut changed = Vec::new();
+        for (&id, player) in &self.players {
+            match base.players.get(&id) {
+                Some(previous) if same_entity(previous, player) => {
+                    if !same_position(previous, player) {
+                        moved.push((id, player.x, player.y));
+                    }
+                }
+                _ => changed.push((id, player.clone())),
+            }
+        }
+        let removed = base
+            .players
+            .keys()
+            .filter(|id| !self.players.contains_key(id))
+            .copied()
+            .collect();
+        StateDelta {
+            base_sequence: base.sequence,
+            sequence: self.sequence,
+            tick: self.tick,
+            moved,
+            changed,
+            removed,
+            ball: if same_position(&base.ball, &self.ball) {
+                None
+            } else {
+                Some((self.ball.x, self.ball.y))
+            },
+            time_left: self.time_left,
+            overtime: self.overtime,
+            kickoff: self.kickoff,
+            pings: (base.pings != self.pings).then(|| self.pings.clone()),
+        }
+    }
+
+    /// State between this one and `next`, `fraction` of the way towards it.
+    /// Players who aren't in this state yet are placed where they are in `next`.
+    pub fn interpolate(&self, next: &NetworkState, fraction: f32) -> NetworkState {
+        let players = next
+            .players
+            .iter()
+            .map(|(&id, player)| {
+                let player = match self.players.get(&id) {
+                    Some(previous) => lerp(previous, player, fraction),
+                    None => player.clone(),
+                };
+                (id, player)
+            })
+            .collect();
+        NetworkState {
+            players,
+            ball: lerp(&self.ball, &next.ball, fraction),
+            ..next.clone()
+        }
+    }
+
+    /// State the host had when it sent `delta`, given this is its base.
+    pub fn apply(&self, delta: &StateDelta) -> NetworkState {
+        let mut players = self.players.clone();
+        for id in &delta.removed {
+            players.remove(id);
+        }
+        for (id, player) in &delta.changed {
+            players.insert(*id, player.clone());
+        }
+        for &(id, x, y) in &delta.moved {
+            if let Some(player) = players.get_mut(&id) {
+                player.x = x;
+                player.y = y;
+            }
+        }
+        let mut ball = self.ball.clone();
+        if let Some((x, y)) = delta.ball {
+            ball.x = x;
+            ball.y = y;
+        }
+        NetworkState {
+            sequence: delta.sequence,
+            tick: delta.tick,
+            players,
+            ball,
+            time_left: delta.time_left,
+            overtime: delta.overtime,
+            kickoff: delta.kickoff,
+            pings: delta.pings.clone().unwrap_or_else(|| self.pings.clone()),
+        }
+    }
+}
+
+fn lerp(from: &Circle, to: &Circle, fraction: f32) -> Circle {
+    Circle {
+        x: from.x + (to.x - from.x) * fraction,
+        y: from.y + (to.y - from.y) * fraction,
+        ..to.clone()
+    }
+}
+
+fn same_position(a: &Circle, b: &Circle) -> bool {
+    a.x == b.x && a.y == b.y
+}
+
+// everything but the position
+fn same_entity(a: &Circle, b: &Circle) -> bool {
+    a.radius == b.radius
+        && a.red == b.red
+        && a.player_number == b.player_number
+        && a.nickname == b.nickname
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circle(x: f32, y: f32, red: bool, player_number: i32) -> Circle {
+        Circle {
+            x,
+            y,
+            radius: 15.0,
+            red,
+            player_number,
+            nickname: format!("player {player_number}"),
+        }
+    }
+
+    fn state(sequence: u32, players: Vec<(u32, Circle)>, ball: Circle) -> NetworkState {
+        NetworkState {
+            sequence,
+            tick: sequence * 2,
+            players: players.into_iter().collect(),
+            ball,
+            time_left: Some(600 - sequence),
+            overtime: false,
+            kickoff: None,
+            pings: BTreeMap::new(),
+        }
+    }
+
+    fn assert_same(a: &NetworkState, b: &NetworkState) {
+        assert_eq!(
+            bincode::serialize(a).unwrap(),
+            bincode::serialize(b).unwrap()
+        );
+    }
+
+    #[test]
+    fn delta_round_trip() {
+        let base = state(
+            1,
+            vec![
+                (1, circle(100.0, 100.0, true, 1)),
+                (2, circle(200.0, 100.0, false, 1)),
+                (3, circle(300.0, 100.0, false, 2)),
+            ],
+            circle(50.0, 50.0, false, -1),
+        );
+        let mut next = state(
+            2,
+            vec![
+                // moved
+                (1, circle(110.0, 90.0, true, 1)),
+                // switched team
+                (2, circle(200.0, 100.0, true, 2)),
+                // joined, while 3 left
+                (4, circle(400.0, 100.0, false, 1)),
+            ],
+            circle(60.0, 50.0, false, -1),
+        );
+        next.kickoff = Some(true);
+        next.pings.insert(
+            1,
+            Ping {
+                round_trip: 40,
+                jitter: 2,
+            },
+        );
+
+        let delta = next.delta_from(&base);
+        assert_eq!(delta.base_sequence, 1);
+        assert_eq!(delta.moved, vec![(1, 110.0, 90.0)]);
+        assert_eq!(
+            delta.changed.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+        assert_eq!(delta.removed, vec![3]);
+        assert_eq!(delta.ball, Some((60.0, 50.0)));
+        assert!(delta.pings.is_some());
+        assert_same(&base.apply(&delta), &next);
+    }
+
+    #[test]
+    fn unchanged_state_sends_no_entities() {
+        let base = state(
+            1,
+            vec![(1, circle(100.0, 100.0, true, 1))],
+            circle(50.0, 50.0, false, -1),
+        );
+        let next = NetworkState {
+            sequence: 2,
+            ..base.clone()
+        };
+
+        let delta = next.delta_from(&base);
+        assert!(delta.moved.is_empty());
+        assert!(delta.changed.is_empty());
+        assert!(delta.removed.is_empty());
+        assert!(delta.ball.is_none());
+        assert!(delta.pings.is_none());
+        assert_same(&base.apply(&delta), &next);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::game::constants::{
+    PITCH_HEIGHT, PITCH_LEFT_LINE, PITCH_RIGHT_LINE, PITCH_TOP_LINE, PITCH_WIDTH,
+};
+
+/// Kickoff slots of a team, as `(depth, width)` fractions of its own half:
+/// depth 0.0 is the team's goal line and 1.0 the halfway line,
+/// width 0.0 is the top touchline and 1.0 the bottom one.
+/// Slots are kept clear of the centre circle, so nobody starts inside a kickoff barrier.
+type Formation = &'static [(f32, f32)];
+
+/// Formation for each team size, starting with a single player.
+const FORMATIONS: [Formation; 5] = [
+    // lone player in front of the goal
+    &[(0.4, 0.5)],
+    // 1-1
+    &[(0.2, 0.5), (0.55, 0.5)],
+    // 1-2
+    &[(0.2, 0.5), (0.55, 0.25), (0.55, 0.75)],
+    // diamond
+    &[(0.15, 0.5), (0.4, 0.2), (0.4, 0.8), (0.6, 0.5)],
+    // 2-1-2
+    &[(0.2, 0.3), (0.2, 0.7), (0.4, 0.5), (0.6, 0.2), (0.6, 0.8)],
+];
+
+/// Teams bigger than the predefined formations are lined up in columns of this many players.
+const GRID_COLUMN_SIZE: usize = 3;
+
+/// Position of the player taking given slot in a team of `team_size` players.
+pub fn kickoff_position(red: bool, slot: usize, team_size: usize) -> (f32, f32) {
+    let (depth, width) = match FORMATIONS.get(team_size.saturating_sub(1)) {
+        Some(formation) => formation[slot.min(formation.len() - 1)],
+        None => grid_slot(slot, team_size),
+    };
+    let depth = depth * PITCH_WIDTH / 2.0;
+    let x = if red {
+        PITCH_LEFT_LINE + depth
+    } else {
+        PITCH_RIGHT_LINE - depth
+    };
+    (x, PITCH_TOP_LINE + width * PITCH_HEIGHT)
+}
+
+fn grid_slot(slot: usize, team_size: usize) -> (f32, f32) {
+    let slot = slot.min(team_size - 1);
+    let columns = team_size.div_ceil(GRID_COLUMN_SIZE);
+    let column = slot / GRID_COLUMN_SIZE;
+    let row = slot % GRID_COLUMN_SIZE;
+    let rows = GRID_COLUMN_SIZE.min(team_size - column * GRID_COLUMN_SIZE);
+    let depth = 0.1 + 0.55 * column as f32 / (columns - 1).max(1) as f32;
+    let width = (row + 1) as f32 / (rows + 1) as f32;
+    (depth, width)
+}
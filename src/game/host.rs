@@ -1,26 +1,31 @@
 use crate::game::constants::{
-    BALL_GROUP, BALL_RADIUS, BALL_TOP_SPEED, GOAL_BREADTH, GOAL_DEPTH, GOAL_POSTS_GROUP, MAX_GOALS,
-    PITCH_BOTTOM_LINE, PITCH_HEIGHT, PITCH_LEFT_LINE, PITCH_LINES_GROUP, PITCH_LINE_HEIGHT,
-    PITCH_LINE_WIDTH, PITCH_RIGHT_LINE, PITCH_TOP_LINE, PITCH_VERTICAL_LINE_HEIGHT, PITCH_WIDTH,
-    PLAYERS_GROUP, PLAYER_ACCELERATION, PLAYER_DIAMETER, PLAYER_RADIUS, PLAYER_TOP_SPEED,
-    RESET_TIME, SHOOTING_DISTANCE, STADIUM_HEIGHT, STADIUM_WALLS_GROUP, STADIUM_WIDTH,
+    FIXED_TIMESTEP, GOAL_BREADTH, HEARTBEAT_INTERVAL, INPUT_REDUNDANCY, MAX_CHAT_RATE,
+    MAX_INPUT_RATE, MAX_MESSAGE_ERRORS, MAX_MESSAGE_RATE, MAX_QUEUED_INPUTS, MAX_RATE_VIOLATIONS,
+    MAX_STEPS_PER_TICK, MIGRATION_SNAPSHOT_INTERVAL, NOTICE_TIME, PING_INTERVAL, PITCH_BOTTOM_LINE,
+    PITCH_LEFT_LINE, PITCH_LINE_WIDTH, PITCH_RIGHT_LINE, PITCH_TOP_LINE, PLAYER_TIMEOUT,
+    RECONNECT_GRACE, STADIUM_HEIGHT, STADIUM_WIDTH, STALE_CONNECTION_TIME, STATE_HISTORY,
+    TICKS_PER_SECOND,
 };
+use crate::game::delta::NetworkState;
 use crate::game::input::{local_player_input, PlayerInput};
-use crate::game::utils::{Arbiter, Circle, Edge, Message, Player, Score};
-use crate::game::{rendering, Game};
-use crate::utils::global_window;
-use rapier2d::dynamics::{
-    CCDSolver, IntegrationParameters, IslandManager, JointSet, RigidBody, RigidBodyBuilder,
-    RigidBodyHandle, RigidBodySet,
+use crate::game::latency::{Latency, Ping};
+use crate::game::migration::{MigratingPlayer, Migration};
+use crate::game::protocol::Handshake;
+use crate::game::rate_limit::MessageRates;
+use crate::game::replay::Replay;
+use crate::game::simulation::{Simulation, SimulationEvent, Snapshot};
+use crate::game::utils::{
+    sanitize_chat_message, ChatLine, Circle, ClientMessage, Edge, LobbyPlayer, MatchSettings,
+    Message, Score,
 };
-use rapier2d::geometry::{
-    BroadPhase, ColliderBuilder, ColliderSet, InteractionGroups, NarrowPhase,
-};
-use rapier2d::pipeline::PhysicsPipeline;
-use rapier2d::prelude::*;
-use std::cell::RefCell;
-use std::collections::HashMap;
+use crate::game::{protocol, rendering, Game};
+use crate::utils::{global_window, now_seconds};
+use log::{error, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::rc::Rc;
+use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
 use wasm_peers::one_to_many::MiniServer;
 use wasm_peers::{ConnectionType, SessionId, UserId};
@@ -28,6 +33,9 @@ use web_sys::CanvasRenderingContext2d;
 
 pub struct HostGame {
     inner: Rc<RefCell<HostGameInner>>,
+    // interval keeping players hearing from the host while its tab is in the background,
+    // as animation frames, and with them ticks, stop there
+    heartbeat: Option<(i32, Closure<dyn FnMut()>)>,
 }
 
 impl HostGame {
@@ -35,85 +43,139 @@ impl HostGame {
         session_id: SessionId,
         connection_type: ConnectionType,
         signaling_server_url: &str,
+        settings: MatchSettings,
+        nickname: String,
+        migration: Option<Migration>,
     ) -> HostGame {
         HostGame {
             inner: Rc::new(RefCell::new(HostGameInner::new(
                 session_id,
                 connection_type,
                 signaling_server_url,
+                settings,
+                nickname,
+                migration,
             ))),
+            heartbeat: None,
+        }
+    }
+
+    fn start_heartbeat(&mut self) {
+        let host_game = self.inner.clone();
+        let heartbeat = Closure::wrap(Box::new(move || {
+            let inner = &mut *host_game.borrow_mut();
+            let now = now_seconds();
+            inner.ping_players(now);
+            inner.send_migration_snapshot(now);
+        }) as Box<dyn FnMut()>);
+        match global_window().set_interval_with_callback_and_timeout_and_arguments_0(
+            heartbeat.as_ref().unchecked_ref(),
+            (HEARTBEAT_INTERVAL * 1000.0) as i32,
+        ) {
+            Ok(handle) => self.heartbeat = Some((handle, heartbeat)),
+            Err(error) => error!("failed to start heartbeat: {error:?}"),
+        }
+    }
+
+    /// Recording of the match so far, serialized to a replay file.
+    pub fn replay_file(&self) -> String {
+        self.inner.borrow().replay.to_json()
+    }
+}
+
+impl Drop for HostGame {
+    fn drop(&mut self) {
+        if let Some((handle, _)) = self.heartbeat.take() {
+            global_window().clear_interval_with_handle(handle);
         }
     }
 }
 
 impl Game for HostGame {
     fn init(&mut self) {
-        let host_player = self.inner.borrow_mut().create_player(
-            PITCH_LEFT_LINE + 2.0 * PLAYER_DIAMETER,
-            STADIUM_HEIGHT / 2.0,
-            true,
-            1,
-        );
-        self.inner.borrow_mut().host_player = Some(host_player);
-
-        let host_game = self.inner.clone();
-        let on_open_callback = move |user_id| {
-            let game_state = Message::GameInit {
-                edges: host_game.borrow().get_edge_entities(),
-                goal_posts: host_game.borrow().get_goal_posts_entities(),
-                players: host_game.borrow().get_player_entities(),
-                ball: host_game.borrow().get_ball_entity(),
-            };
-            let game_state = serde_json::to_string(&game_state).unwrap();
-            let _ = host_game
-                .borrow()
-                .mini_server
-                .send_message(user_id, &game_state);
-            host_game.borrow_mut().game_started = true;
+        {
+            let inner = &mut *self.inner.borrow_mut();
+            // host taking over a match keeps their player
+            if !inner.simulation.has_host_player() {
+                inner.simulation.add_host_player(&inner.nickname);
+            }
+        }
 
-            let red_players_count = 1 + host_game
-                .borrow()
-                .players
-                .values()
-                .filter(|player| player.red)
-                .count();
-            let blue_players_count = host_game
-                .borrow()
-                .players
-                .values()
-                .filter(|player| !player.red)
-                .count();
-
-            let player = if red_players_count < blue_players_count {
-                host_game.borrow_mut().create_player(
-                    PITCH_LEFT_LINE + 2.0 * PLAYER_DIAMETER,
-                    STADIUM_HEIGHT / 2.0,
-                    true,
-                    red_players_count + 1,
-                )
-            } else {
-                host_game.borrow_mut().create_player(
-                    PITCH_RIGHT_LINE - 2.0 * PLAYER_DIAMETER,
-                    STADIUM_HEIGHT / 2.0,
-                    false,
-                    blue_players_count + 1,
-                )
-            };
-            host_game.borrow_mut().players.insert(user_id, player);
-        };
+        // peers are let into the match once their handshake checks out
+        let on_open_callback = |_user_id| {};
 
         let host_game = self.inner.clone();
         let on_message_callback = move |user_id, message: String| {
-            let input = serde_json::from_str::<PlayerInput>(&message).unwrap();
-            host_game
-                .borrow_mut()
-                .players
-                .get_mut(&user_id)
-                .expect("no player instance for this user_id")
-                .set_input(input);
+            let inner = &mut *host_game.borrow_mut();
+            let now = now_seconds();
+            if let Some(last_heard) = inner.disconnected.get_mut(&user_id) {
+                *last_heard = now;
+                return;
+            }
+            // peers not in the match, having just connected or timed out, can only shake hands
+            match inner.last_heard.get_mut(&user_id) {
+                Some(last_heard) => *last_heard = now,
+                None => {
+                    inner.shake_hands(user_id, &message, now);
+                    return;
+                }
+            }
+            let message = match protocol::decode::<ClientMessage>(&message) {
+                Ok(message) => message,
+                Err(error) => {
+                    inner.message_error(user_id, &error, now);
+                    return;
+                }
+            };
+            if !inner.accept_message(user_id, &message, now) {
+                return;
+            }
+            match message {
+                ClientMessage::Hello {
+                    reconnect_token,
+                    nickname,
+                    player_id,
+                } => {
+                    inner.player_joined(user_id, reconnect_token, &nickname, player_id, now);
+                }
+                ClientMessage::Input {
+                    inputs,
+                    sequence,
+                    ack,
+                } => {
+                    inner.queue_inputs(user_id, inputs, sequence);
+                    if let Some(ack) = ack {
+                        inner.acked.insert(user_id, ack);
+                    }
+                }
+                ClientMessage::Pong { sent_at } => {
+                    // a time from the future is no measurement at all
+                    if sent_at <= now {
+                        inner
+                            .latencies
+                            .entry(user_id)
+                            .or_default()
+                            .add_sample(now - sent_at);
+                    }
+                }
+                ClientMessage::KeyframeRequest => {
+                    inner.acked.remove(&user_id);
+                }
+                ClientMessage::RematchVote => inner.rematch_vote(user_id),
+                ClientMessage::Ready { ready } => inner.set_ready(user_id, ready),
+                ClientMessage::Chat { text, team_only } => {
+                    inner.chat(Some(user_id), &text, team_only);
+                }
+            }
         };
 
+        {
+            let inner = &mut *self.inner.borrow_mut();
+            inner.snapshot = inner.simulation.snapshot();
+            inner.start_recording();
+        }
         self.inner.borrow().draw();
+        self.start_heartbeat();
 
         self.inner
             .borrow_mut()
@@ -127,37 +189,104 @@ impl Game for HostGame {
     }
 
     fn ended(&self) -> bool {
-        self.inner.borrow().get_game_ended()
+        self.inner.borrow().simulation.get_game_ended()
+    }
+
+    fn rematch(&mut self) {
+        self.inner.borrow_mut().rematch();
+    }
+
+    fn lobby(&self) -> Option<Vec<LobbyPlayer>> {
+        let inner = self.inner.borrow();
+        (!inner.game_started).then(|| inner.lobby.clone())
+    }
+
+    fn set_ready(&mut self, ready: bool) {
+        if ready {
+            self.inner.borrow_mut().start_match();
+        }
+    }
+
+    fn send_chat(&mut self, text: &str, team_only: bool) {
+        self.inner.borrow_mut().chat(None, text, team_only);
+    }
+
+    fn take_chat(&mut self) -> Vec<ChatLine> {
+        std::mem::take(&mut self.inner.borrow_mut().chat_lines)
+    }
+
+    // host is the one players migrate away from
+    fn take_migration(&mut self) -> Option<Migration> {
+        None
+    }
+
+    fn incompatible_host(&self) -> Option<String> {
+        None
     }
 }
 
 pub struct HostGameInner {
-    host_player: Option<Player>,
-    players: HashMap<UserId, Player>,
+    session_id: String,
+    nickname: String,
+    simulation: Simulation,
+    snapshot: Snapshot,
     edges: Vec<Edge>,
     goal_posts: Vec<Circle>,
-    ball_body_handle: RigidBodyHandle,
-    arbiter: Arbiter,
+    // input each player holds in the current step, inputs waiting for the next steps
+    // and the sequence number of the newest input received
+    inputs: HashMap<UserId, PlayerInput>,
+    input_queues: HashMap<UserId, VecDeque<PlayerInput>>,
+    input_sequences: HashMap<UserId, u32>,
+    // messages received from each player in the current second
+    message_rates: HashMap<UserId, MessageRates>,
+    // when each connected player last sent anything, in seconds
+    last_heard: HashMap<UserId, f64>,
+    // malformed messages received from each peer with the time of the latest one,
+    // and peers disconnected for sending too many with when they last sent anything
+    message_errors: HashMap<UserId, (u32, f64)>,
+    disconnected: HashMap<UserId, f64>,
+    // players dropped for going silent, with when they last sent anything, who may still be
+    // catching up on what they missed before they introduce themselves again
+    dropped: HashMap<UserId, f64>,
+    // round trip time of each player's connection, measured every `PING_INTERVAL`
+    latencies: HashMap<UserId, Latency>,
+    last_ping_time: Option<f64>,
+    // reconnect token of each player, and team and number of those who left with the time they did
+    tokens: HashMap<UserId, String>,
+    departed: HashMap<String, (f64, bool, usize)>,
+    // players of the match this host took over, by their id there, with the time it happened
+    migrated_players: HashMap<u32, (f64, MigratingPlayer)>,
+    // connected players, longest connected first, who take over in this order if the host leaves
+    connection_order: Vec<UserId>,
+    last_migration_snapshot_time: Option<f64>,
+    // players who want to play again after the match ended
+    rematch_votes: HashSet<UserId>,
+    // players ready for the match to start, and the lobby as last sent to them
+    ready: HashSet<UserId>,
+    lobby: Vec<LobbyPlayer>,
+    // chat messages for the host not yet shown
+    chat_lines: Vec<ChatLine>,
+    // tick when a player left, with their team and number, for the on screen notice
+    departure: Option<(u32, bool, i32)>,
+    // recently sent game states, oldest first, and the newest of them each player acknowledged
+    sent_states: VecDeque<NetworkState>,
+    acked: HashMap<UserId, u32>,
+    state_sequence: u32,
+    replay: Replay,
+
+    // fixed timestep bookkeeping, in seconds
+    last_tick_time: Option<f64>,
+    accumulator: f64,
 
     // required by networking crate
     mini_server: MiniServer,
+    // false while players are gathering in the lobby
     game_started: bool,
 
-    // stuff required by physics engine
-    rigid_body_set: RigidBodySet,
-    collider_set: ColliderSet,
-    integration_parameters: IntegrationParameters,
-    physics_pipeline: PhysicsPipeline,
-    island_manager: IslandManager,
-    broad_phase: BroadPhase,
-    narrow_phase: NarrowPhase,
-    joint_set: JointSet,
-    ccd_solver: CCDSolver,
-    physics_hooks: (),
-    event_handler: (),
     // drawing stuff
     context: CanvasRenderingContext2d,
     player_input: Rc<RefCell<PlayerInput>>,
+    match_running: Rc<Cell<bool>>,
 }
 
 impl HostGameInner {
@@ -165,18 +294,37 @@ impl HostGameInner {
         session_id: SessionId,
         connection_type: ConnectionType,
         signaling_server_url: &str,
+        settings: MatchSettings,
+        nickname: String,
+        migration: Option<Migration>,
     ) -> HostGameInner {
+        let session_string = session_id.as_str().to_string();
         let mini_server = MiniServer::new(signaling_server_url, session_id, connection_type)
             .expect("failed to create network manager");
 
-        let mut rigid_body_set = RigidBodySet::new();
-        let mut collider_set = ColliderSet::new();
-
-        let edges = HostGameInner::create_pitch_lines(&mut collider_set);
-        let goal_posts = HostGameInner::create_goals_posts(&mut collider_set);
-        HostGameInner::create_stadium_walls(&mut collider_set);
-
-        let ball_body_handle = HostGameInner::create_ball(&mut rigid_body_set, &mut collider_set);
+        let now = now_seconds();
+        let (mut simulation, game_started, migrated_players) = match migration {
+            Some(migration) => {
+                let simulation =
+                    Simulation::from_migration(&migration.snapshot, migration.player_id);
+                let migrated_players = migration
+                    .snapshot
+                    .players
+                    .into_iter()
+                    .filter(|player| Some(player.id) != migration.player_id)
+                    .map(|player| (player.id, (now, player)))
+                    .collect();
+                (
+                    simulation,
+                    migration.snapshot.game_started,
+                    migrated_players,
+                )
+            }
+            None => (Simulation::new(settings), false, HashMap::new()),
+        };
+        let snapshot = simulation.snapshot();
+        let edges = simulation.get_edge_entities();
+        let goal_posts = simulation.get_goal_posts_entities();
 
         let document = global_window().document().unwrap();
         let context = {
@@ -195,518 +343,612 @@ impl HostGameInner {
         };
         context.set_text_align("center");
         context.set_text_baseline("middle");
+        let match_running = Rc::new(Cell::new(false));
 
         HostGameInner {
-            mini_server,
-            game_started: false,
-            host_player: None,
-            players: HashMap::new(),
+            session_id: session_string,
+            nickname,
+            simulation,
+            snapshot,
             edges,
             goal_posts,
-            ball_body_handle,
-            arbiter: Arbiter::new(),
-            rigid_body_set,
-            collider_set,
-            integration_parameters: IntegrationParameters::default(),
-            physics_pipeline: PhysicsPipeline::new(),
-            island_manager: IslandManager::new(),
-            broad_phase: BroadPhase::new(),
-            narrow_phase: NarrowPhase::new(),
-            joint_set: JointSet::new(),
-            ccd_solver: CCDSolver::new(),
-            physics_hooks: (),
-            event_handler: (),
+            inputs: HashMap::new(),
+            input_queues: HashMap::new(),
+            input_sequences: HashMap::new(),
+            message_rates: HashMap::new(),
+            last_heard: HashMap::new(),
+            message_errors: HashMap::new(),
+            disconnected: HashMap::new(),
+            dropped: HashMap::new(),
+            latencies: HashMap::new(),
+            last_ping_time: None,
+            tokens: HashMap::new(),
+            departed: HashMap::new(),
+            migrated_players,
+            connection_order: Vec::new(),
+            last_migration_snapshot_time: None,
+            rematch_votes: HashSet::new(),
+            ready: HashSet::new(),
+            lobby: Vec::new(),
+            chat_lines: Vec::new(),
+            departure: None,
+            sent_states: VecDeque::new(),
+            acked: HashMap::new(),
+            state_sequence: 0,
+            replay: Replay::new(),
+            last_tick_time: None,
+            accumulator: 0.0,
+            mini_server,
+            game_started,
             context,
-            player_input: local_player_input(),
+            player_input: local_player_input(match_running.clone()),
+            match_running,
         }
     }
 
+    /// Advances the simulation by as many fixed steps as fit in the time elapsed since
+    /// the previous tick, so the match runs at the same pace on every display refresh rate.
     pub(self) fn tick(&mut self) {
-        self.check_timer();
-        self.host_player
-            .as_mut()
-            .unwrap()
-            .set_input(*self.player_input.borrow());
-        self.parse_input();
-
-        HostGameInner::limit_speed(
-            &mut self.rigid_body_set[self.ball_body_handle],
-            BALL_TOP_SPEED,
-        );
-
-        self.physics_pipeline.step(
-            &vector![0.0, 0.0],
-            &self.integration_parameters,
-            &mut self.island_manager,
-            &mut self.broad_phase,
-            &mut self.narrow_phase,
-            &mut self.rigid_body_set,
-            &mut self.collider_set,
-            &mut self.joint_set,
-            &mut self.ccd_solver,
-            &self.physics_hooks,
-            &self.event_handler,
-        );
+        let now = now_seconds();
+        let elapsed = self.last_tick_time.map_or(0.0, |last| now - last);
+        self.last_tick_time = Some(now);
+        self.remove_silent_players(now);
+        self.ping_players(now);
+        self.send_migration_snapshot(now);
+        self.match_running
+            .set(self.game_started && !self.simulation.get_game_ended());
+        if !self.game_started {
+            self.update_lobby();
+            self.draw();
+            return;
+        }
+        // finished match stays as it ended until a rematch
+        if self.simulation.get_game_ended() {
+            self.accumulator = 0.0;
+            self.draw();
+            return;
+        }
+        // don't try to catch up after long pauses, for e.g. when the tab was in background
+        let max_accumulated = MAX_STEPS_PER_TICK as f64 * FIXED_TIMESTEP as f64;
+        self.accumulator = (self.accumulator + elapsed).min(max_accumulated);
+
+        let host_input = *self.player_input.borrow();
+        let mut events = Vec::new();
+        let mut stepped = false;
+        while self.accumulator >= FIXED_TIMESTEP as f64 {
+            self.accumulator -= FIXED_TIMESTEP as f64;
+            // each input is processed in a step of its own, the last one is held when none came
+            for (user_id, queue) in self.input_queues.iter_mut() {
+                if let Some(input) = queue.pop_front() {
+                    self.inputs.insert(*user_id, input);
+                }
+            }
+            let mut snapshot = self.simulation.step(host_input, &self.inputs);
+            events.append(&mut snapshot.events);
+            self.snapshot = snapshot;
+            stepped = true;
+        }
 
-        self.host_send_state();
+        if stepped {
+            self.snapshot.events = events;
+            self.host_send_state();
+        }
         self.draw();
     }
 
-    fn create_pitch_lines(collider_set: &mut ColliderSet) -> Vec<Edge> {
-        let mut edges = Vec::new();
-        let mut create_line_closure = |width, height, x, y, white, membership, filter| {
-            let cuboid_collider = ColliderBuilder::cuboid(width / 2.0, height / 2.0)
-                .collision_groups(InteractionGroups::new(membership, filter))
-                .translation(vector![x, y])
-                .build();
-            edges.push(Edge::new(
-                cuboid_collider.translation().x,
-                cuboid_collider.translation().y,
-                width,
-                height,
-                white,
-            ));
-            collider_set.insert(cuboid_collider);
+    /// Answers a peer's handshake with the host's own, and lets them into the match
+    /// if they speak the same protocol.
+    fn shake_hands(&mut self, user_id: UserId, message: &str, now: f64) {
+        let handshake = match Handshake::decode(message) {
+            Ok(handshake) => handshake,
+            // messages a dropped player sent before learning about it are no fault of theirs
+            Err(_) if self.dropped.contains_key(&user_id) => {
+                self.dropped.insert(user_id, now);
+                return;
+            }
+            Err(error) => {
+                self.message_error(user_id, &error, now);
+                return;
+            }
         };
+        self.dropped.remove(&user_id);
+        let local = Handshake::local();
+        let _ = self.mini_server.send_message(user_id, &local.encode());
+        if !handshake.compatible() {
+            warn!(
+                "player runs version {}, which is incompatible with the host's {}",
+                handshake.version(),
+                local.version()
+            );
+            return;
+        }
+        let game_init = protocol::encode(&self.game_init_message());
+        let _ = self.mini_server.send_message(user_id, &game_init);
+        // player is created once they introduce themselves, as they may be coming back
+        self.last_heard.insert(user_id, now);
+    }
 
-        // left higher pitch line
-        create_line_closure(
-            PITCH_LINE_WIDTH,
-            PITCH_VERTICAL_LINE_HEIGHT,
-            PITCH_LEFT_LINE,
-            (STADIUM_HEIGHT - GOAL_BREADTH - PITCH_VERTICAL_LINE_HEIGHT) / 2.0,
-            true,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-        // left lower pitch line
-        create_line_closure(
-            PITCH_LINE_WIDTH,
-            PITCH_VERTICAL_LINE_HEIGHT,
-            PITCH_LEFT_LINE,
-            (STADIUM_HEIGHT + GOAL_BREADTH + PITCH_VERTICAL_LINE_HEIGHT) / 2.0,
-            true,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-        // left goal
-        create_line_closure(
-            PITCH_LINE_WIDTH,
-            GOAL_BREADTH,
-            PITCH_LEFT_LINE - GOAL_DEPTH,
-            STADIUM_HEIGHT / 2.0,
-            false,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-        create_line_closure(
-            GOAL_DEPTH,
-            PITCH_LINE_HEIGHT,
-            PITCH_LEFT_LINE - GOAL_DEPTH / 2.0,
-            (STADIUM_HEIGHT - GOAL_BREADTH) / 2.0,
-            false,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-        create_line_closure(
-            GOAL_DEPTH,
-            PITCH_LINE_HEIGHT,
-            PITCH_LEFT_LINE - GOAL_DEPTH / 2.0,
-            (STADIUM_HEIGHT + GOAL_BREADTH) / 2.0,
-            false,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-
-        // right higher pitch line
-        create_line_closure(
-            PITCH_LINE_WIDTH,
-            PITCH_VERTICAL_LINE_HEIGHT,
-            PITCH_RIGHT_LINE,
-            (STADIUM_HEIGHT - GOAL_BREADTH - PITCH_VERTICAL_LINE_HEIGHT) / 2.0,
-            true,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-        // right lower pitch line
-        create_line_closure(
-            PITCH_LINE_WIDTH,
-            PITCH_VERTICAL_LINE_HEIGHT,
-            PITCH_RIGHT_LINE,
-            (STADIUM_HEIGHT + GOAL_BREADTH + PITCH_VERTICAL_LINE_HEIGHT) / 2.0,
-            true,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-        // right goal
-        create_line_closure(
-            PITCH_LINE_WIDTH,
-            GOAL_BREADTH,
-            PITCH_RIGHT_LINE + GOAL_DEPTH,
-            STADIUM_HEIGHT / 2.0,
-            false,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-        create_line_closure(
-            GOAL_DEPTH,
-            PITCH_LINE_HEIGHT,
-            PITCH_RIGHT_LINE + GOAL_DEPTH / 2.0,
-            (STADIUM_HEIGHT - GOAL_BREADTH) / 2.0,
-            false,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-        create_line_closure(
-            GOAL_DEPTH,
-            PITCH_LINE_HEIGHT,
-            PITCH_RIGHT_LINE + GOAL_DEPTH / 2.0,
-            (STADIUM_HEIGHT + GOAL_BREADTH) / 2.0,
-            false,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-
-        // top pitch line`
-        create_line_closure(
-            PITCH_WIDTH,
-            PITCH_LINE_HEIGHT,
-            STADIUM_WIDTH / 2.0,
-            PITCH_TOP_LINE,
-            true,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
-
-        // bottom pitch line
-        create_line_closure(
-            PITCH_WIDTH,
-            PITCH_LINE_HEIGHT,
-            STADIUM_WIDTH / 2.0,
-            PITCH_BOTTOM_LINE,
-            true,
-            PITCH_LINES_GROUP,
-            PITCH_LINES_GROUP,
-        );
+    /// Gives a player who has just connected their old place back
+    /// if they left recently, or a place in the smaller team otherwise.
+    fn player_joined(
+        &mut self,
+        user_id: UserId,
+        reconnect_token: String,
+        nickname: &str,
+        player_id: Option<u32>,
+        now: f64,
+    ) {
+        if self.tokens.contains_key(&user_id) {
+            return;
+        }
+        let previous_connection = self
+            .tokens
+            .iter()
+            .find(|(_, token)| **token == reconnect_token)
+            .map(|(&previous_id, _)| previous_id)
+            // the same browser can be in the match twice, from two tabs
+            .filter(|previous_id| {
+                self.last_heard
+                    .get(previous_id)
+                    .is_none_or(|&last_heard| now - last_heard > STALE_CONNECTION_TIME)
+            });
+        // a player connecting again has to be sent the lobby, even if it's unchanged
+        self.lobby.clear();
+        if let Some(previous_id) = previous_connection {
+            self.tokens.remove(&previous_id);
+            self.ready.remove(&previous_id);
+            // a vote cast from the previous connection still stands, under the new one
+            if self.rematch_votes.remove(&previous_id) {
+                self.rematch_votes.insert(user_id);
+            }
+            self.last_heard.remove(&previous_id);
+            self.message_errors.remove(&previous_id);
+            self.inputs.remove(&previous_id);
+            self.input_queues.remove(&previous_id);
+            self.input_sequences.remove(&previous_id);
+            self.message_rates.remove(&previous_id);
+            self.latencies.remove(&previous_id);
+            self.acked.remove(&previous_id);
+            self.simulation.reassign_player(&previous_id, user_id);
+            for connection in self.connection_order.iter_mut() {
+                if *connection == previous_id {
+                    *connection = user_id;
+                }
+            }
+        } else {
+            let migrated_player = player_id.and_then(|id| self.migrated_players.remove(&id));
+            match (migrated_player, self.departed.remove(&reconnect_token)) {
+                (Some((_, player)), _) => self.simulation.restore_migrated_player(user_id, &player),
+                (None, Some((_, red, number))) => self
+                    .simulation
+                    .restore_player(user_id, red, number, nickname),
+                (None, None) => self.simulation.add_player(user_id, nickname),
+            }
+            self.connection_order.push(user_id);
+        }
+        self.tokens.insert(user_id, reconnect_token);
+    }
 
-        edges
-    }
-
-    fn create_goals_posts(collider_set: &mut ColliderSet) -> Vec<Circle> {
-        let mut goal_posts = Vec::new();
-
-        let mut create_post_closure = |x, y, red| {
-            let ball_collider = ColliderBuilder::ball(BALL_RADIUS)
-                .collision_groups(InteractionGroups::new(GOAL_POSTS_GROUP, GOAL_POSTS_GROUP))
-                .translation(vector![x, y])
-                .build();
-            goal_posts.push(Circle::new(
-                ball_collider.translation().x,
-                ball_collider.translation().y,
-                BALL_RADIUS,
-                red,
-                -1,
-            ));
-            collider_set.insert(ball_collider);
-        };
-        // left red goal
-        create_post_closure(
-            PITCH_LEFT_LINE,
-            PITCH_TOP_LINE + PITCH_HEIGHT / 2.0 - GOAL_BREADTH / 2.0,
-            true,
-        );
-        create_post_closure(
-            PITCH_LEFT_LINE,
-            PITCH_TOP_LINE + PITCH_HEIGHT / 2.0 + GOAL_BREADTH / 2.0,
-            true,
-        );
+    fn ping_players(&mut self, now: f64) {
+        if self
+            .last_ping_time
+            .is_some_and(|last_ping_time| now - last_ping_time < PING_INTERVAL)
+        {
+            return;
+        }
+        self.last_ping_time = Some(now);
+        let ping = protocol::encode(&Message::Ping { sent_at: now });
+        self.broadcast(&ping);
+    }
 
-        // right blue goal
-        create_post_closure(
-            PITCH_RIGHT_LINE,
-            PITCH_TOP_LINE + PITCH_HEIGHT / 2.0 - GOAL_BREADTH / 2.0,
-            false,
-        );
-        create_post_closure(
-            PITCH_RIGHT_LINE,
-            PITCH_TOP_LINE + PITCH_HEIGHT / 2.0 + GOAL_BREADTH / 2.0,
-            false,
-        );
+    /// Lets players know how to carry on with the match, and who takes it over, if the host leaves.
+    fn send_migration_snapshot(&mut self, now: f64) {
+        if self
+            .last_migration_snapshot_time
+            .is_some_and(|last_time| now - last_time < MIGRATION_SNAPSHOT_INTERVAL)
+        {
+            return;
+        }
+        self.last_migration_snapshot_time = Some(now);
+        let succession = self
+            .connection_order
+            .iter()
+            .filter_map(|user_id| self.simulation.get_player_body(user_id))
+            .map(|player_body| player_body.id)
+            .collect();
+        let snapshot =
+            self.simulation
+                .migration_snapshot(&self.session_id, self.game_started, succession);
+        let message = protocol::encode(&Message::Migration { snapshot });
+        self.broadcast(&message);
+    }
 
-        goal_posts
+    /// Ping of each player measured so far, by player id.
+    fn pings(&self) -> BTreeMap<u32, Ping> {
+        self.simulation
+            .get_lineup()
+            .into_iter()
+            .filter_map(|(user_id, player)| {
+                let ping = self.latencies.get(&user_id?)?.ping()?;
+                Some((player.id, ping))
+            })
+            .collect()
     }
 
-    fn create_stadium_walls(collider_set: &mut ColliderSet) {
-        let mut create_wall_closure = |width, height, x, y| {
-            let cuboid_collider = ColliderBuilder::cuboid(width / 2.0, height / 2.0)
-                .collision_groups(InteractionGroups::new(
-                    STADIUM_WALLS_GROUP,
-                    STADIUM_WALLS_GROUP,
-                ))
-                .translation(vector![x, y])
-                .build();
-            collider_set.insert(cuboid_collider);
+    /// Whether the message fits in the player's per second limit for its kind, inputs, chat
+    /// or anything else. Those beyond it are dropped, and the player is flagged for every second
+    /// they go over a limit, until they're disconnected after `MAX_RATE_VIOLATIONS` of them.
+    fn accept_message(&mut self, user_id: UserId, message: &ClientMessage, now: f64) -> bool {
+        let rates = self.message_rates.entry(user_id).or_default();
+        let (rate, limit) = match message {
+            ClientMessage::Input { .. } => (&mut rates.input, MAX_INPUT_RATE),
+            ClientMessage::Chat { .. } => (&mut rates.chat, MAX_CHAT_RATE),
+            _ => (&mut rates.other, MAX_MESSAGE_RATE),
         };
-        // left stadium wall
-        create_wall_closure(0.0, STADIUM_HEIGHT, 0.0, STADIUM_HEIGHT / 2.0);
-
-        // right stadium wall
-        create_wall_closure(0.0, STADIUM_HEIGHT, STADIUM_WIDTH, STADIUM_HEIGHT / 2.0);
-
-        // top stadium wall
-        create_wall_closure(STADIUM_WIDTH, 0.0, STADIUM_WIDTH / 2.0, 0.0);
-
-        // bottom stadium wall
-        create_wall_closure(STADIUM_WIDTH, 0.0, STADIUM_WIDTH / 2.0, STADIUM_HEIGHT);
-    }
-
-    fn create_player(&mut self, x: f32, y: f32, is_red: bool, number: usize) -> Player {
-        const COLLISION_GROUP: u32 =
-            PLAYERS_GROUP | STADIUM_WALLS_GROUP | BALL_GROUP | GOAL_POSTS_GROUP;
-        let player_rigid_body = RigidBodyBuilder::new_dynamic()
-            .linear_damping(1.0)
-            .translation(vector![x, y])
-            .build();
-        let player_rigid_body = Rc::new(RefCell::new(player_rigid_body));
-        let player_collider = ColliderBuilder::ball(PLAYER_RADIUS)
-            .collision_groups(InteractionGroups::new(COLLISION_GROUP, COLLISION_GROUP))
-            .restitution(0.7)
-            .build();
-        let player_body_handle: RigidBodyHandle = self
-            .rigid_body_set
-            .insert(player_rigid_body.borrow().to_owned());
-        self.collider_set.insert_with_parent(
-            player_collider,
-            player_body_handle,
-            &mut self.rigid_body_set,
-        );
-        Player::new(player_body_handle, PLAYER_RADIUS, is_red, number)
+        let violations = rate.violations;
+        let accepted = rate.allow(now, limit);
+        if rate.violations == violations {
+            return accepted;
+        }
+        let violations = rates.violations();
+        warn!("player {user_id:?} sends messages faster than allowed, {violations} times so far");
+        if violations >= MAX_RATE_VIOLATIONS {
+            error!("disconnecting player {user_id:?} for flooding the host with messages");
+            self.disconnect(user_id, now);
+            return false;
+        }
+        accepted
     }
 
-    fn create_ball(
-        rigid_body_set: &mut RigidBodySet,
-        collider_set: &mut ColliderSet,
-    ) -> RigidBodyHandle {
-        const COLLISION_GROUP: u32 =
-            BALL_GROUP | PLAYERS_GROUP | PITCH_LINES_GROUP | GOAL_POSTS_GROUP;
+    /// Queues the inputs the player sends for the first time, `sequence` numbering the last one.
+    /// Every input is repeated in a few messages, in case some of them get lost.
+    fn queue_inputs(&mut self, user_id: UserId, inputs: Vec<PlayerInput>, sequence: u32) {
+        let newest = self.input_sequences.get(&user_id).copied().unwrap_or(0);
+        let new_inputs = unseen_inputs(inputs, sequence, newest);
+        if new_inputs.is_empty() {
+            return;
+        }
+        self.input_sequences.insert(user_id, sequence);
+        let queue = self.input_queues.entry(user_id).or_default();
+        enqueue_inputs(queue, new_inputs);
+    }
 
-        let ball_rigid_body = RigidBodyBuilder::new_dynamic()
-            .linear_damping(0.3)
-            .translation(vector![STADIUM_WIDTH / 2.0, STADIUM_HEIGHT / 2.0])
-            .build();
-        let ball_rigid_body = Rc::new(RefCell::new(ball_rigid_body));
-        let ball_collider = ColliderBuilder::ball(BALL_RADIUS)
-            .density(0.5)
-            .collision_groups(InteractionGroups::new(COLLISION_GROUP, COLLISION_GROUP))
-            .restitution(0.7)
-            .build();
-        let ball_body_handle: RigidBodyHandle =
-            rigid_body_set.insert(ball_rigid_body.borrow().to_owned());
-        collider_set.insert_with_parent(ball_collider, ball_body_handle, rigid_body_set);
+    fn lobby_players(&self) -> Vec<LobbyPlayer> {
+        let mut lobby: Vec<LobbyPlayer> = self
+            .simulation
+            .get_lineup()
+            .into_iter()
+            .map(|(user_id, player)| LobbyPlayer {
+                red: player.red,
+                number: player.number as i32,
+                nickname: player.nickname.clone(),
+                // host is ready by definition, as they start the match
+                ready: user_id.is_none_or(|user_id| self.ready.contains(&user_id)),
+                is_host: user_id.is_none(),
+            })
+            .collect();
+        lobby.sort_by_key(|player| (!player.red, player.number));
+        lobby
+    }
 
-        ball_body_handle
+    /// Tells players about changes in the lobby, along with the pitch they are lined up on.
+    fn update_lobby(&mut self) {
+        let lobby = self.lobby_players();
+        if lobby == self.lobby {
+            return;
+        }
+        self.lobby = lobby.clone();
+        self.snapshot = self.simulation.snapshot();
+        self.send_message_to_all(&Message::Lobby { players: lobby });
+        self.send_state();
     }
 
-    fn host_send_state(&mut self) {
-        let game_state = if self.arbiter.send_score_message {
-            self.arbiter.send_score_message = false;
-            Message::GoalScored {
-                red_scored: self.get_red_scored(),
-                score: self.get_score(),
-            }
+    fn set_ready(&mut self, user_id: UserId, ready: bool) {
+        if self.game_started || !self.tokens.contains_key(&user_id) {
+            return;
+        }
+        if ready {
+            self.ready.insert(user_id);
         } else {
-            Message::GameState {
-                players: self.get_player_entities(),
-                ball: self.get_ball_entity(),
-            }
-        };
-        let game_state = serde_json::to_string(&game_state).unwrap();
-
-        self.mini_server.send_message_to_all(&game_state);
-    }
-
-    fn parse_input(&mut self) {
-        let mut players: Vec<_> = self.players.values_mut().collect();
-        players.push(self.host_player.as_mut().unwrap());
-        for player in players {
-            let player_last_tick_shot = player.last_tick_shot;
-            let input = player.get_input();
-            let body_handle = player.rigid_body_handle;
-
-            if input.shoot {
-                if !player_last_tick_shot {
-                    let px;
-                    let py;
-                    {
-                        let player_body = &self.rigid_body_set[body_handle];
-                        px = player_body.translation().x;
-                        py = player_body.translation().y;
-                    }
+            self.ready.remove(&user_id);
+        }
+    }
 
-                    let ball_body = &mut self.rigid_body_set[self.ball_body_handle];
-                    let bx = ball_body.translation().x;
-                    let by = ball_body.translation().y;
-
-                    let dx = bx - px;
-                    let dy = by - py;
-                    let dist_sqr = dx * dx + dy * dy;
-                    if dist_sqr <= SHOOTING_DISTANCE * SHOOTING_DISTANCE {
-                        let angle = crate::game::utils::angle(px, py, bx, by);
-                        let x_speed =
-                            BALL_TOP_SPEED * (std::f32::consts::PI * (angle / 180.0)).cos();
-                        let y_speed =
-                            BALL_TOP_SPEED * (std::f32::consts::PI * (angle / 180.0)).sin();
-                        ball_body.set_linvel(vector![x_speed, y_speed], true);
-                    }
-                    player.set_last_tick_shot(true);
-                }
-            } else {
-                player.set_last_tick_shot(false);
-            }
+    /// Kicks the match off, once everyone in the lobby is ready.
+    fn start_match(&mut self) {
+        if self.game_started || !self.lobby_players().iter().all(|player| player.ready) {
+            return;
+        }
+        self.game_started = true;
+        self.ready.clear();
+        self.lobby.clear();
+        // line everyone up again, as formations change while teams fill up
+        self.simulation.restart();
+        self.snapshot = self.simulation.snapshot();
+        self.start_recording();
+        self.send_message_to_all(&Message::MatchStarted);
+    }
 
-            let player_body = &mut self.rigid_body_set[body_handle];
+    /// Starts a fresh replay of the match, beginning with everything needed to draw it.
+    fn start_recording(&mut self) {
+        self.replay = Replay::new();
+        let game_init = self.game_init_message();
+        self.replay.record(self.simulation.get_tick(), &game_init);
+    }
 
-            if input.up {
-                player_body.apply_impulse(vector![0.0, -PLAYER_ACCELERATION], true);
-            } else if input.down {
-                player_body.apply_impulse(vector![0.0, PLAYER_ACCELERATION], true);
+    /// Passes chat message of given player, `None` being the host, on to its recipients.
+    fn chat(&mut self, author: Option<UserId>, text: &str, team_only: bool) {
+        let text = sanitize_chat_message(text);
+        let line = match self.simulation.get_player(author.as_ref()) {
+            Some(player) if !text.is_empty() => ChatLine {
+                red: player.red,
+                player_number: player.number as i32,
+                nickname: player.nickname.clone(),
+                text,
+                team_only,
+            },
+            _ => return,
+        };
+        if !team_only {
+            self.chat_lines.push(line.clone());
+            self.send_message_to_all(&Message::Chat { line });
+            return;
+        }
+        // team messages are sent one by one and left out of the replay
+        let message = protocol::encode(&Message::Chat { line: line.clone() });
+        for (user_id, player) in self.simulation.get_lineup() {
+            if player.red != line.red {
+                continue;
             }
-
-            if input.left {
-                player_body.apply_impulse(vector![-PLAYER_ACCELERATION, 0.0], true);
-            } else if input.right {
-                player_body.apply_impulse(vector![PLAYER_ACCELERATION, 0.0], true);
+            match user_id {
+                Some(user_id) => {
+                    let _ = self.mini_server.send_message(user_id, &message);
+                }
+                None => self.chat_lines.push(line.clone()),
             }
-
-            HostGameInner::limit_speed(player_body, PLAYER_TOP_SPEED);
-        }
-    }
-
-    fn limit_speed(rigid_body: &mut RigidBody, top_speed: f32) {
-        let x_speed = rigid_body.linvel().x;
-        let y_speed = rigid_body.linvel().y;
-        let speed = f32::sqrt(x_speed * x_speed + y_speed * y_speed);
-        if speed > top_speed {
-            let speed_normalized = rigid_body.linvel().normalize();
-            rigid_body.set_linvel(
-                vector![
-                    speed_normalized.x * top_speed,
-                    speed_normalized.y * top_speed
-                ],
-                true,
-            );
         }
     }
 
-    fn check_timer(&mut self) {
-        if self.arbiter.game_ended {
+    fn rematch_vote(&mut self, user_id: UserId) {
+        if !self.simulation.get_game_ended() || !self.tokens.contains_key(&user_id) {
             return;
         }
-        if self.arbiter.reset_timer > 0 {
-            self.timer_tick();
-        } else if self.goal_scored() {
-            self.arbiter.reset_timer = RESET_TIME;
+        if self.rematch_votes.insert(user_id) {
+            self.rematch_votes_changed();
         }
     }
 
-    fn goal_scored(&mut self) -> bool {
-        let ball_body = &mut self.rigid_body_set[self.ball_body_handle];
-        let x = ball_body.translation().x;
-        if x < PITCH_LEFT_LINE {
-            self.arbiter.set_blue_scored();
-            true
-        } else if x > PITCH_RIGHT_LINE {
-            self.arbiter.set_red_scored();
-            true
+    /// Lets everyone know how the vote stands, and starts the rematch once every player voted.
+    fn rematch_votes_changed(&mut self) {
+        if !self.tokens.is_empty() && self.rematch_votes.len() >= self.tokens.len() {
+            self.rematch();
         } else {
-            false
+            self.send_rematch_votes();
         }
     }
 
-    fn timer_tick(&mut self) {
-        self.arbiter.reset_timer -= 1;
-        if self.arbiter.reset_timer == 0 {
-            self.arbiter.reset_who_scored();
-            self.check_ending();
-            self.reset_game();
-        }
+    fn send_rematch_votes(&mut self) {
+        let message = Message::RematchVotes {
+            votes: self.rematch_votes.len() as u32,
+            players: self.tokens.len() as u32,
+        };
+        self.send_message_to_all(&message);
     }
 
-    fn check_ending(&mut self) {
-        if self.arbiter.red_score == MAX_GOALS || self.arbiter.blue_score == MAX_GOALS {
-            self.arbiter.game_ended = true;
-            let game_state = serde_json::to_string(&Message::GameEnded).unwrap();
-            self.mini_server.send_message_to_all(&game_state);
+    /// Starts the finished match over, in the same session and with everyone still connected.
+    fn rematch(&mut self) {
+        if !self.simulation.get_game_ended() {
+            return;
         }
+        self.simulation.restart();
+        self.snapshot = self.simulation.snapshot();
+        self.start_recording();
+        self.rematch_votes.clear();
+        self.departure = None;
+        self.send_message_to_all(&Message::Rematch);
     }
 
-    fn reset_game(&mut self) {
-        {
-            let ball_body = &mut self.rigid_body_set[self.ball_body_handle];
-            ball_body.set_position(
-                Isometry::new(vector![STADIUM_WIDTH / 2.0, STADIUM_HEIGHT / 2.0], 0.0),
-                false,
-            );
-            ball_body.set_linvel(vector![0.0, 0.0], false);
+    /// Drops players the host hasn't heard from in `PLAYER_TIMEOUT` seconds,
+    /// as a peer closing their tab isn't reported any other way.
+    fn remove_silent_players(&mut self, now: f64) {
+        let silent_players: Vec<UserId> = self
+            .last_heard
+            .iter()
+            .filter(|(_, &last_heard)| now - last_heard > PLAYER_TIMEOUT)
+            .map(|(&user_id, _)| user_id)
+            .collect();
+        // a player whose connection is still open, like in a tab left in the background,
+        // gets to know they have to introduce themselves again to come back
+        let dropped = protocol::encode(&Message::Dropped);
+        for user_id in silent_players {
+            let _ = self.mini_server.send_message(user_id, &dropped);
+            self.dropped.insert(user_id, now);
+            self.remove_player(user_id, now);
         }
+        self.departed
+            .retain(|_, (left_at, _, _)| now - *left_at <= RECONNECT_GRACE);
+        self.migrated_players
+            .retain(|_, (migrated_at, _)| now - *migrated_at <= RECONNECT_GRACE);
+        // connections that have gone quiet are most likely closed
+        self.disconnected
+            .retain(|_, last_heard| now - *last_heard <= PLAYER_TIMEOUT);
+        self.dropped
+            .retain(|_, last_heard| now - *last_heard <= PLAYER_TIMEOUT);
+        let last_heard = &self.last_heard;
+        self.message_errors.retain(|user_id, (_, last_error)| {
+            last_heard.contains_key(user_id) || now - *last_error <= PLAYER_TIMEOUT
+        });
+    }
 
-        for player in self.players.values_mut() {
-            player.reset_position(&mut self.rigid_body_set, 0.0, 0.0);
+    /// Takes the player off the pitch and forgets their connection,
+    /// keeping their place for a while in case they come back.
+    fn remove_player(&mut self, user_id: UserId, now: f64) {
+        self.last_heard.remove(&user_id);
+        self.message_errors.remove(&user_id);
+        self.inputs.remove(&user_id);
+        self.input_queues.remove(&user_id);
+        self.input_sequences.remove(&user_id);
+        self.message_rates.remove(&user_id);
+        self.latencies.remove(&user_id);
+        self.connection_order
+            .retain(|connection| *connection != user_id);
+        self.acked.remove(&user_id);
+        let token = self.tokens.remove(&user_id);
+        self.ready.remove(&user_id);
+        self.rematch_votes.remove(&user_id);
+        let introduced = token.is_some();
+        if let Some((red, number)) = self.simulation.remove_player(&user_id) {
+            if let Some(token) = token {
+                self.departed.insert(token, (now, red, number));
+            }
+            let player_number = number as i32;
+            self.departure = Some((self.snapshot.tick, red, player_number));
+            self.send_message_to_all(&Message::PlayerLeft { red, player_number });
+        }
+        // the players left may all have voted already, the rematch starts without the leaver
+        if introduced && self.simulation.get_game_ended() {
+            self.rematch_votes_changed();
         }
-        self.host_player
-            .as_mut()
-            .unwrap()
-            .reset_position(&mut self.rigid_body_set, 0.0, 0.0);
     }
 
-    fn get_player_entities(&self) -> Vec<Circle> {
-        let mut v: Vec<Circle> = self
-            .players
-            .values()
-            .map(|player| player.to_circle(&self.rigid_body_set))
-            .collect();
-        v.push(
-            self.host_player
-                .as_ref()
-                .unwrap()
-                .to_circle(&self.rigid_body_set),
-        );
-        v
+    /// Counts a message from the player that couldn't be read. A player sending too many
+    /// is most likely not running this game at all, so they're disconnected.
+    fn message_error(&mut self, user_id: UserId, error: &dyn fmt::Display, now: f64) {
+        let (errors, last_error) = self.message_errors.entry(user_id).or_insert((0, now));
+        *errors += 1;
+        *last_error = now;
+        let errors = *errors;
+        warn!("malformed message from player {user_id:?} ({errors} so far): {error}");
+        if errors >= MAX_MESSAGE_ERRORS {
+            error!("disconnecting player {user_id:?} after {errors} malformed messages");
+            self.disconnect(user_id, now);
+        }
     }
 
-    fn get_ball_entity(&self) -> Circle {
-        let brb = &self.rigid_body_set[self.ball_body_handle];
-        Circle::new(
-            brb.translation().x,
-            brb.translation().y,
-            BALL_RADIUS,
-            false,
-            -1,
-        )
+    /// Drops the peer from the match for good. Peer connections can't be closed from here,
+    /// so whatever else they send is ignored, until they go silent like a closed connection.
+    fn disconnect(&mut self, user_id: UserId, now: f64) {
+        self.disconnected.insert(user_id, now);
+        self.remove_player(user_id, now);
     }
 
-    fn get_edge_entities(&self) -> Vec<Edge> {
-        self.edges.clone()
+    fn game_init_message(&self) -> Message {
+        Message::GameInit {
+            edges: self.simulation.get_edge_entities(),
+            goal_posts: self.simulation.get_goal_posts_entities(),
+            players: self.simulation.get_player_entities(),
+            ball: self.simulation.get_ball_entity(),
+            score: self.simulation.get_score(),
+            game_ended: self.simulation.get_game_ended(),
+            game_started: self.game_started,
+        }
     }
 
-    fn get_goal_posts_entities(&self) -> Vec<Circle> {
-        self.goal_posts.clone()
-    }
+    fn host_send_state(&mut self) {
+        let goal_scored = self
+            .snapshot
+            .events
+            .iter()
+            .any(|event| matches!(event, SimulationEvent::GoalScored { .. }));
+        // goal message replaces the state update for this tick
+        if !goal_scored {
+            self.send_state();
+        }
 
-    fn get_red_scored(&self) -> bool {
-        self.arbiter.red_scored
+        for event in std::mem::take(&mut self.snapshot.events) {
+            let message = match event {
+                SimulationEvent::GoalScored { red_scored } => Message::GoalScored {
+                    red_scored,
+                    score: self.snapshot.score.clone(),
+                },
+                SimulationEvent::GameEnded => Message::GameEnded,
+                SimulationEvent::PenaltyKick {
+                    red_kicking,
+                    shooter_number,
+                    round,
+                } => Message::PenaltyKick {
+                    red_kicking,
+                    shooter_number,
+                    round,
+                },
+                SimulationEvent::PenaltyTaken {
+                    red_kicking,
+                    scored,
+                } => Message::PenaltyTaken {
+                    red_kicking,
+                    scored,
+                    penalties: self
+                        .snapshot
+                        .penalty_status
+                        .as_ref()
+                        .map_or_else(|| Score::new(0, 0), |status| status.penalties.clone()),
+                },
+            };
+            self.send_message_to_all(&message);
+        }
     }
 
-    fn get_blue_scored(&self) -> bool {
-        self.arbiter.blue_scored
+    /// Sends every connected player the current game state, as a delta against the newest
+    /// state they acknowledged or in full if there's none, and records it in the match replay.
+    fn send_state(&mut self) {
+        let game_state = Message::GameState {
+            players: self.snapshot.players.clone(),
+            ball: self.snapshot.ball.clone(),
+            time_left: self.snapshot.time_left,
+            overtime: self.snapshot.overtime,
+        };
+        self.replay.record(self.snapshot.tick, &game_state);
+
+        self.state_sequence += 1;
+        let state = NetworkState::from_snapshot(self.state_sequence, &self.snapshot, self.pings());
+        for user_id in self.last_heard.keys() {
+            let base = self.acked.get(user_id).and_then(|&sequence| {
+                self.sent_states
+                    .iter()
+                    .find(|sent| sent.sequence == sequence)
+            });
+            let player_body = self.simulation.get_player_body(user_id);
+            // inputs still queued haven't been processed yet
+            let input_ack = self.input_sequences.get(user_id).map(|&sequence| {
+                let queued = self.input_queues.get(user_id).map_or(0, VecDeque::len);
+                sequence - queued as u32
+            });
+            let message = match base {
+                Some(base) => Message::StateDelta {
+                    delta: state.delta_from(base),
+                    player_body,
+                    input_ack,
+                },
+                None => Message::Keyframe {
+                    state: state.clone(),
+                    player_body,
+                    input_ack,
+                },
+            };
+            // allow some messages to fail, the next state follows shortly
+            let _ = self
+                .mini_server
+                .send_message(*user_id, &protocol::encode(&message));
+        }
+        self.sent_states.push_back(state);
+        if self.sent_states.len() > STATE_HISTORY {
+            self.sent_states.pop_front();
+        }
     }
 
-    fn get_score(&self) -> Score {
-        Score::new(self.arbiter.red_score, self.arbiter.blue_score)
+    /// Sends message to every connected player and records it in the match replay.
+    fn send_message_to_all(&mut self, message: &Message) {
+        self.replay.record(self.snapshot.tick, message);
+        let message = protocol::encode(message);
+        self.broadcast(&message);
     }
 
-    fn get_game_ended(&self) -> bool {
-        self.arbiter.game_ended
+    /// Sends encoded message to the players in the match, leaving out peers
+    /// that haven't shaken hands or have been disconnected.
+    fn broadcast(&self, message: &str) {
+        for user_id in self.last_heard.keys() {
+            let _ = self.mini_server.send_message(*user_id, message);
+        }
     }
 
     fn draw(&self) {
@@ -726,25 +968,182 @@ impl HostGameInner {
         rendering::draw_goals(&self.context, &self.goal_posts);
         rendering::draw_score(
             &self.context,
-            &self.get_score(),
+            &self.snapshot.score,
             STADIUM_WIDTH as f64,
             PITCH_TOP_LINE as f64,
         );
-        rendering::draw_players(&self.context, &self.get_player_entities());
-        rendering::draw_ball(&self.context, &self.get_ball_entity());
-        if self.get_red_scored() {
+        if self.snapshot.time_left.is_some() || self.snapshot.overtime {
+            rendering::draw_clock(
+                &self.context,
+                self.snapshot
+                    .time_left
+                    .map(|time_left| time_left / TICKS_PER_SECOND),
+                self.snapshot.overtime,
+                STADIUM_WIDTH as f64,
+                PITCH_TOP_LINE as f64,
+            );
+        }
+        rendering::draw_players(&self.context, &self.snapshot.players);
+        rendering::draw_ball(&self.context, &self.snapshot.ball);
+        if self.snapshot.red_scored {
             rendering::draw_red_scored(&self.context, STADIUM_WIDTH as f64, STADIUM_HEIGHT as f64);
         }
-        if self.get_blue_scored() {
+        if self.snapshot.blue_scored {
             rendering::draw_blue_scored(&self.context, STADIUM_WIDTH as f64, STADIUM_HEIGHT as f64);
         }
-        if self.get_game_ended() {
+        if let Some((tick, red, player_number)) = self.departure {
+            if self.snapshot.tick < tick + NOTICE_TIME {
+                rendering::draw_player_left(
+                    &self.context,
+                    red,
+                    player_number,
+                    STADIUM_WIDTH as f64,
+                    PITCH_TOP_LINE as f64,
+                );
+            }
+        }
+        if let Some(status) = self.snapshot.penalty_status.as_ref() {
+            rendering::draw_penalty_status(
+                &self.context,
+                status,
+                STADIUM_WIDTH as f64,
+                STADIUM_HEIGHT as f64,
+                PITCH_BOTTOM_LINE as f64,
+            );
+        }
+        if self.snapshot.game_ended {
             rendering::draw_game_ended(
                 &self.context,
-                &self.get_score(),
+                &self.snapshot.score,
+                self.snapshot.overtime,
+                self.snapshot
+                    .penalty_status
+                    .as_ref()
+                    .map(|status| &status.penalties),
+                STADIUM_WIDTH as f64,
+                STADIUM_HEIGHT as f64,
+            );
+            rendering::draw_rematch_votes(
+                &self.context,
+                self.rematch_votes.len() as u32,
+                self.tokens.len() as u32,
+                STADIUM_WIDTH as f64,
+                STADIUM_HEIGHT as f64,
+            );
+        }
+        if self.player_input.borrow().player_list {
+            let pings = self.pings();
+            let players: Vec<(&Circle, Option<Ping>)> = self
+                .snapshot
+                .players
+                .iter()
+                .zip(&self.snapshot.player_ids)
+                .map(|(player, id)| (player, pings.get(id).copied()))
+                .collect();
+            rendering::draw_player_list(
+                &self.context,
+                &players,
                 STADIUM_WIDTH as f64,
                 STADIUM_HEIGHT as f64,
             );
         }
     }
 }
+
+/// Inputs of a message numbered up to `sequence`, oldest first, that come after
+/// the `newest` one already received.
+fn unseen_inputs(inputs: Vec<PlayerInput>, sequence: u32, newest: u32) -> Vec<PlayerInput> {
+    let mut new_inputs: Vec<PlayerInput> = inputs
+        .into_iter()
+        .rev()
+        // no more than a well-behaved player repeats
+        .take(INPUT_REDUNDANCY)
+        .zip((0..=sequence).rev())
+        .take_while(|&(_, input_sequence)| input_sequence > newest)
+        .map(|(input, _)| input)
+        .collect();
+    new_inputs.reverse();
+    new_inputs
+}
+
+/// Appends inputs to the queue, dropping the oldest beyond `MAX_QUEUED_INPUTS`.
+/// A shot among those dropped is carried over to the oldest input kept, so no press is lost.
+fn enqueue_inputs(queue: &mut VecDeque<PlayerInput>, inputs: Vec<PlayerInput>) {
+    queue.extend(inputs);
+    let mut dropped_shot = false;
+    while queue.len() > MAX_QUEUED_INPUTS {
+        dropped_shot |= queue.pop_front().is_some_and(|input| input.shoot);
+    }
+    if let Some(oldest) = queue.front_mut() {
+        oldest.shoot |= dropped_shot;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Input telling its number apart by the directions held.
+    fn numbered(number: u32) -> PlayerInput {
+        PlayerInput {
+            up: number & 1 != 0,
+            down: number & 2 != 0,
+            left: number & 4 != 0,
+            right: number & 8 != 0,
+            ..PlayerInput::default()
+        }
+    }
+
+    fn number(input: &PlayerInput) -> u32 {
+        input.up as u32
+            | ((input.down as u32) << 1)
+            | ((input.left as u32) << 2)
+            | ((input.right as u32) << 3)
+    }
+
+    fn numbers(inputs: &[PlayerInput]) -> Vec<u32> {
+        inputs.iter().map(number).collect()
+    }
+
+    fn inputs(first: u32, last: u32) -> Vec<PlayerInput> {
+        (first..=last).map(numbered).collect()
+    }
+
+    #[test]
+    fn repeated_inputs_are_taken_once() {
+        assert_eq!(numbers(&unseen_inputs(inputs(1, 3), 3, 0)), vec![1, 2, 3]);
+        assert_eq!(numbers(&unseen_inputs(inputs(1, 4), 4, 3)), vec![4]);
+        assert!(unseen_inputs(inputs(1, 4), 4, 4).is_empty());
+        // a message overtaken by a newer one brings nothing new
+        assert!(unseen_inputs(inputs(1, 3), 3, 4).is_empty());
+    }
+
+    #[test]
+    fn no_more_inputs_than_redundancy_are_taken() {
+        let unseen = unseen_inputs(inputs(1, 12), 12, 0);
+        assert_eq!(unseen.len(), INPUT_REDUNDANCY);
+        assert_eq!(numbers(&unseen).last(), Some(&12));
+    }
+
+    #[test]
+    fn overflowing_queue_drops_the_oldest_inputs() {
+        let mut queue = VecDeque::new();
+        enqueue_inputs(&mut queue, inputs(1, MAX_QUEUED_INPUTS as u32));
+        enqueue_inputs(&mut queue, inputs(13, 14));
+        assert_eq!(queue.len(), MAX_QUEUED_INPUTS);
+        assert_eq!(queue.front().map(number), Some(3));
+        assert_eq!(queue.back().map(number), Some(14));
+        assert!(queue.iter().all(|input| !input.shoot));
+    }
+
+    #[test]
+    fn dropped_shot_is_carried_over() {
+        let mut queue: VecDeque<PlayerInput> = inputs(1, MAX_QUEUED_INPUTS as u32).into();
+        queue[0].shoot = true;
+        enqueue_inputs(&mut queue, vec![numbered(13)]);
+        assert_eq!(queue.len(), MAX_QUEUED_INPUTS);
+        assert_eq!(queue.front().map(number), Some(2));
+        assert!(queue[0].shoot);
+        assert_eq!(queue.iter().filter(|input| input.shoot).count(), 1);
+    }
+}
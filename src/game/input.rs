@@ -1,10 +1,10 @@
 use crate::utils::global_window;
 use serde::{Deserialize, Serialize};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use wasm_bindgen::closure::Closure;
 use wasm_bindgen::JsCast;
-use web_sys::KeyboardEvent;
+use web_sys::{Document, HtmlInputElement, KeyboardEvent};
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub struct PlayerInput {
@@ -13,14 +13,27 @@ pub struct PlayerInput {
     pub(crate) left: bool,
     pub(crate) right: bool,
     pub(crate) shoot: bool,
+    /// Player list is shown while held, it only matters locally.
+    #[serde(skip)]
+    pub(crate) player_list: bool,
 }
 
-pub(crate) fn local_player_input() -> Rc<RefCell<PlayerInput>> {
+/// Tracks the local keyboard, `match_running` is kept up to date by the game so Tab
+/// only stops moving the focus around the page while it matters.
+pub(crate) fn local_player_input(match_running: Rc<Cell<bool>>) -> Rc<RefCell<PlayerInput>> {
     let keys_pressed = Rc::new(RefCell::new(PlayerInput::default()));
     let document = global_window().document().unwrap();
     {
         let keys_pressed = keys_pressed.clone();
+        let document = document.clone();
         let keydown_listener = Closure::wrap(Box::new(move |event: KeyboardEvent| {
+            // typing into text fields, like the chat, doesn't move the player
+            let typing = event
+                .target()
+                .is_some_and(|target| target.dyn_ref::<HtmlInputElement>().is_some());
+            if typing {
+                return;
+            }
             let key = event.key();
             if key.as_str() == "Spacebar" || key.as_str() == " " {
                 keys_pressed.borrow_mut().shoot = true;
@@ -38,6 +51,13 @@ pub(crate) fn local_player_input() -> Rc<RefCell<PlayerInput>> {
                 "d" | "ArrowRight" => {
                     keys_pressed.borrow_mut().right = true;
                 }
+                "Tab" => {
+                    // keep focus where it is, outside a match Tab still moves around the page
+                    if match_running.get() || canvas_focused(&document) {
+                        event.prevent_default();
+                    }
+                    keys_pressed.borrow_mut().player_list = true;
+                }
                 _ => {}
             }
         }) as Box<dyn FnMut(KeyboardEvent)>);
@@ -66,6 +86,9 @@ pub(crate) fn local_player_input() -> Rc<RefCell<PlayerInput>> {
                 "d" | "ArrowRight" => {
                     keys_pressed.borrow_mut().right = false;
                 }
+                "Tab" => {
+                    keys_pressed.borrow_mut().player_list = false;
+                }
                 _ => {}
             }
         }) as Box<dyn FnMut(KeyboardEvent)>);
@@ -76,3 +99,9 @@ pub(crate) fn local_player_input() -> Rc<RefCell<PlayerInput>> {
     }
     keys_pressed
 }
+
+fn canvas_focused(document: &Document) -> bool {
+    document
+        .active_element()
+        .is_some_and(|element| element.id() == "canvas")
+}
@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+
+// weights of a new sample in the rolling averages, as in TCP's retransmission timer
+const ROUND_TRIP_WEIGHT: f64 = 1.0 / 8.0;
+const JITTER_WEIGHT: f64 = 1.0 / 4.0;
+
+/// Player's ping as published to everyone, in milliseconds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ping {
+    pub round_trip: u32,
+    pub jitter: u32,
+}
+
+/// Rolling estimate of a connection's round trip time and its variation, in seconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Latency {
+    round_trip: Option<f64>,
+    jitter: f64,
+}
+
+impl Latency {
+    pub fn add_sample(&mut self, round_trip: f64) {
+        match self.round_trip.as_mut() {
+            Some(average) => {
+                self.jitter += ((round_trip - *average).abs() - self.jitter) * JITTER_WEIGHT;
+                *average += (round_trip - *average) * ROUND_TRIP_WEIGHT;
+            }
+            None => {
+                self.round_trip = Some(round_trip);
+                self.jitter = round_trip / 2.0;
+            }
+        }
+    }
+
+    /// Estimate in milliseconds, once there's been at least one sample.
+    pub fn ping(&self) -> Option<Ping> {
+        self.round_trip.map(|round_trip| Ping {
+            round_trip: (round_trip * 1000.0).round() as u32,
+            jitter: (self.jitter * 1000.0).round() as u32,
+        })
+    }
+}
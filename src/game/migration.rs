@@ -0,0 +1,60 @@
+use crate::game::utils::{MatchSettings, Score};
+use serde::{Deserialize, Serialize};
+use wasm_peers::SessionId;
+
+/// Position and velocity of a body.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct BodyMotion {
+    pub x: f32,
+    pub y: f32,
+    pub x_speed: f32,
+    pub y_speed: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MigratingPlayer {
+    pub id: u32,
+    pub red: bool,
+    pub number: usize,
+    pub nickname: String,
+    pub motion: BodyMotion,
+}
+
+/// Everything a player needs to carry on with the match as its new host, should the host leave.
+/// A penalty shootout in progress is started over.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MigrationSnapshot {
+    /// Session of the host that took the snapshot, successors' sessions are derived from it.
+    pub session_id: String,
+    pub settings: MatchSettings,
+    pub game_started: bool,
+    pub game_ended: bool,
+    pub tick: u32,
+    pub score: Score,
+    pub time_left: Option<u32>,
+    pub overtime: bool,
+    pub ball: BodyMotion,
+    pub players: Vec<MigratingPlayer>,
+    /// Ids of the connected players, the longest connected first, in the order they take over.
+    pub succession: Vec<u32>,
+}
+
+impl MigrationSnapshot {
+    /// Session given successor hosts the match in, every player derives the same one.
+    pub fn successor_session_id(&self, successor: u32) -> SessionId {
+        SessionId::new(format!("{}-{}", self.session_id, successor))
+    }
+}
+
+/// Switch to a new host, agreed on by the players after the previous one was gone for too long.
+#[derive(Clone)]
+pub struct Migration {
+    pub session_id: SessionId,
+    /// Whether the local player is the new host.
+    pub become_host: bool,
+    /// Id the local player had under the previous host.
+    pub player_id: Option<u32>,
+    /// For the new host to rebuild the match from, and for the others to pick
+    /// the next successor with, in case the new host doesn't show up either.
+    pub snapshot: MigrationSnapshot,
+}
@@ -1,14 +1,28 @@
 mod client;
 mod constants;
+mod delta;
+mod formations;
 mod host;
 mod input;
+mod latency;
+mod migration;
+mod prediction;
+mod protocol;
+mod rate_limit;
 mod rendering;
+mod replay;
+mod shootout;
+mod simulation;
 mod utils;
 
 use crate::game::constants::{PITCH_HEIGHT, PITCH_WIDTH, PLAYER_DIAMETER};
 
 pub use crate::game::client::ClientGame;
+pub use crate::game::constants::{MAX_CHAT_MESSAGE_LENGTH, MAX_MATCH_MINUTES};
 pub use crate::game::host::HostGame;
+pub use crate::game::migration::Migration;
+pub use crate::game::replay::{Replay, ReplayViewer};
+pub use crate::game::utils::{ChatLine, LobbyPlayer, MatchSettings, TieBreak};
 
 pub const GAME_CANVAS_WIDTH: f32 = 2.0 * PLAYER_DIAMETER + PITCH_WIDTH + 2.0 * PLAYER_DIAMETER;
 pub const GAME_CANVAS_HEIGHT: f32 = 2.0 * PLAYER_DIAMETER + PITCH_HEIGHT;
@@ -17,6 +31,20 @@ pub trait Game {
     fn init(&mut self);
     fn tick(&mut self);
     fn ended(&self) -> bool;
+    /// Host starts a finished match over, other players vote for it.
+    fn rematch(&mut self);
+    /// Players waiting in the lobby, as long as the match hasn't started.
+    fn lobby(&self) -> Option<Vec<LobbyPlayer>>;
+    /// Marks the local player as (not) ready, host being ready starts the match.
+    fn set_ready(&mut self, ready: bool);
+    /// Sends a chat message to everyone, or only to the local player's team.
+    fn send_chat(&mut self, text: &str, team_only: bool);
+    /// Chat messages received since the previous call.
+    fn take_chat(&mut self) -> Vec<ChatLine>;
+    /// Switch to a new host the players agreed on, after the current one went silent.
+    fn take_migration(&mut self) -> Option<Migration>;
+    /// Version the host runs, if it's one the local game can't play with.
+    fn incompatible_host(&self) -> Option<String>;
 }
 
 pub enum FootballersGame {
@@ -45,4 +73,53 @@ impl Game for FootballersGame {
             FootballersGame::Client(game) => game.ended(),
         }
     }
+
+    fn rematch(&mut self) {
+        match self {
+            FootballersGame::Host(game) => game.rematch(),
+            FootballersGame::Client(game) => game.rematch(),
+        }
+    }
+
+    fn lobby(&self) -> Option<Vec<LobbyPlayer>> {
+        match self {
+            FootballersGame::Host(game) => game.lobby(),
+            FootballersGame::Client(game) => game.lobby(),
+        }
+    }
+
+    fn set_ready(&mut self, ready: bool) {
+        match self {
+            FootballersGame::Host(game) => game.set_ready(ready),
+            FootballersGame::Client(game) => game.set_ready(ready),
+        }
+    }
+
+    fn send_chat(&mut self, text: &str, team_only: bool) {
+        match self {
+            FootballersGame::Host(game) => game.send_chat(text, team_only),
+            FootballersGame::Client(game) => game.send_chat(text, team_only),
+        }
+    }
+
+    fn take_chat(&mut self) -> Vec<ChatLine> {
+        match self {
+            FootballersGame::Host(game) => game.take_chat(),
+            FootballersGame::Client(game) => game.take_chat(),
+        }
+    }
+
+    fn take_migration(&mut self) -> Option<Migration> {
+        match self {
+            FootballersGame::Host(game) => game.take_migration(),
+            FootballersGame::Client(game) => game.take_migration(),
+        }
+    }
+
+    fn incompatible_host(&self) -> Option<String> {
+        match self {
+            FootballersGame::Host(game) => game.incompatible_host(),
+            FootballersGame::Client(game) => game.incompatible_host(),
+        }
+    }
 }
@@ -0,0 +1,168 @@
+use crate::game::constants::{FIXED_TIMESTEP, STADIUM_HEIGHT, STADIUM_WIDTH, STATE_HISTORY};
+use crate::game::input::PlayerInput;
+use crate::game::simulation::Simulation;
+use crate::game::utils::PlayerBody;
+use rapier2d::dynamics::{
+    CCDSolver, IntegrationParameters, IslandManager, JointSet, RigidBodyHandle, RigidBodySet,
+};
+use rapier2d::geometry::{BroadPhase, ColliderHandle, ColliderSet, NarrowPhase};
+use rapier2d::pipeline::PhysicsPipeline;
+use rapier2d::prelude::*;
+use std::collections::VecDeque;
+
+/// Client side copy of the local player's body, moved by their input right away
+/// instead of after a round trip to the host.
+///
+/// Only the stadium, the goal posts and the kickoff barriers are there with it, so whenever
+/// the host's state arrives the body is put where the host has it and the inputs the host
+/// hasn't processed yet are replayed.
+pub struct Prediction {
+    // id of the predicted player, once the host has told which one it is
+    player_id: Option<u32>,
+    body_handle: RigidBodyHandle,
+    red: bool,
+    // same as the host's, keeping the team not taking the kickoff in its own half
+    kickoff_barriers: Vec<(ColliderHandle, bool)>,
+    // inputs sent to the host and not yet processed by it, with their sequence numbers
+    pending_inputs: VecDeque<(u32, PlayerInput)>,
+
+    // stuff required by physics engine
+    rigid_body_set: RigidBodySet,
+    collider_set: ColliderSet,
+    integration_parameters: IntegrationParameters,
+    physics_pipeline: PhysicsPipeline,
+    island_manager: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    joint_set: JointSet,
+    ccd_solver: CCDSolver,
+}
+
+impl Default for Prediction {
+    fn default() -> Self {
+        Prediction::new()
+    }
+}
+
+impl Prediction {
+    pub fn new() -> Prediction {
+        let mut rigid_body_set = RigidBodySet::new();
+        let mut collider_set = ColliderSet::new();
+        Simulation::create_goals_posts(&mut collider_set);
+        Simulation::create_stadium_walls(&mut collider_set);
+        let kickoff_barriers = Simulation::create_kickoff_barriers(&mut collider_set);
+        let body_handle = Simulation::create_player_body(
+            &mut rigid_body_set,
+            &mut collider_set,
+            STADIUM_WIDTH / 2.0,
+            STADIUM_HEIGHT / 2.0,
+            true,
+        );
+        Prediction {
+            player_id: None,
+            body_handle,
+            red: true,
+            kickoff_barriers,
+            pending_inputs: VecDeque::new(),
+            rigid_body_set,
+            collider_set,
+            integration_parameters: IntegrationParameters {
+                dt: FIXED_TIMESTEP,
+                ..IntegrationParameters::default()
+            },
+            physics_pipeline: PhysicsPipeline::new(),
+            island_manager: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            joint_set: JointSet::new(),
+            ccd_solver: CCDSolver::new(),
+        }
+    }
+
+    /// Moves the player by a single step of their input, which has just been sent to the host.
+    pub fn step(&mut self, sequence: u32, input: PlayerInput) {
+        self.pending_inputs.push_back((sequence, input));
+        // host not processing inputs for this long is going to send a state correcting it all
+        if self.pending_inputs.len() > STATE_HISTORY {
+            self.pending_inputs.pop_front();
+        }
+        self.advance(input);
+    }
+
+    /// Puts the player where the host has them, after processing inputs up to `input_ack`,
+    /// and applies the remaining inputs again. Without a body the player isn't in the match.
+    /// `red` is the player's team and `kickoff` the team taking the kickoff in progress.
+    pub fn reconcile(
+        &mut self,
+        player_body: Option<&PlayerBody>,
+        input_ack: Option<u32>,
+        red: bool,
+        kickoff: Option<bool>,
+    ) {
+        let player_body = match player_body {
+            Some(player_body) => player_body,
+            None => {
+                self.player_id = None;
+                self.pending_inputs.clear();
+                return;
+            }
+        };
+        self.player_id = Some(player_body.id);
+        self.set_team(red);
+        Simulation::set_kickoff_barriers(&mut self.collider_set, &self.kickoff_barriers, kickoff);
+        if let Some(input_ack) = input_ack {
+            self.pending_inputs
+                .retain(|&(sequence, _)| sequence > input_ack);
+        }
+        let body = &mut self.rigid_body_set[self.body_handle];
+        body.set_position(
+            Isometry::new(vector![player_body.x, player_body.y], 0.0),
+            true,
+        );
+        body.set_linvel(vector![player_body.x_speed, player_body.y_speed], true);
+        let pending_inputs: Vec<PlayerInput> = self
+            .pending_inputs
+            .iter()
+            .map(|&(_, input)| input)
+            .collect();
+        for input in pending_inputs {
+            self.advance(input);
+        }
+    }
+
+    /// Id and position of the predicted player.
+    pub fn position(&self) -> Option<(u32, f32, f32)> {
+        let body = &self.rigid_body_set[self.body_handle];
+        self.player_id
+            .map(|id| (id, body.translation().x, body.translation().y))
+    }
+
+    /// Kickoff barriers only stop the players of one team, so the body has to be in the right one.
+    fn set_team(&mut self, red: bool) {
+        if self.red == red {
+            return;
+        }
+        self.red = red;
+        for &collider_handle in self.rigid_body_set[self.body_handle].colliders() {
+            self.collider_set[collider_handle]
+                .set_collision_groups(Simulation::player_collision_groups(red));
+        }
+    }
+
+    fn advance(&mut self, input: PlayerInput) {
+        Simulation::move_player(&mut self.rigid_body_set[self.body_handle], input);
+        self.physics_pipeline.step(
+            &vector![0.0, 0.0],
+            &self.integration_parameters,
+            &mut self.island_manager,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            &mut self.joint_set,
+            &mut self.ccd_solver,
+            &(),
+            &(),
+        );
+    }
+}
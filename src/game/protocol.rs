@@ -0,0 +1,174 @@
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Version of the wire format, bump it on every incompatible change to `Message`,
+/// `ClientMessage` or anything they contain.
+pub const PROTOCOL_VERSION: u8 = 11;
+
+/// Identifies the build for people, set with the `BUILD_ID` environment variable at compile time.
+pub const BUILD_ID: &str = match option_env!("BUILD_ID") {
+    Some(build_id) => build_id,
+    None => env!("CARGO_PKG_VERSION"),
+};
+
+/// First message a player and the host send each other, before anything else is exchanged.
+/// Unlike every other message it's plain JSON, a format that must never change,
+/// so that peers running different versions can still tell so.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Handshake {
+    pub protocol_version: u8,
+    pub build_id: String,
+}
+
+impl Handshake {
+    pub fn local() -> Handshake {
+        Handshake {
+            protocol_version: PROTOCOL_VERSION,
+            build_id: BUILD_ID.to_string(),
+        }
+    }
+
+    /// Whether the peer that sent this handshake understands the local messages.
+    pub fn compatible(&self) -> bool {
+        self.protocol_version == PROTOCOL_VERSION
+    }
+
+    /// Version as shown to players.
+    pub fn version(&self) -> String {
+        format!("{} (protocol {})", self.build_id, self.protocol_version)
+    }
+
+    pub fn encode(&self) -> String {
+        serde_json::to_string(self).expect("handshake should always be serializable")
+    }
+
+    pub fn decode(message: &str) -> serde_json::Result<Handshake> {
+        serde_json::from_str(message)
+    }
+}
+
+/// Why a message received from a peer couldn't be read.
+#[derive(Debug)]
+pub enum ProtocolError {
+    Encoding(base64::DecodeError),
+    Empty,
+    Version(u8),
+    Payload(bincode::Error),
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Encoding(error) => write!(f, "message is not valid base64: {error}"),
+            ProtocolError::Empty => write!(f, "message is empty"),
+            ProtocolError::Version(version) => write!(
+                f,
+                "message has protocol version {version}, expected {PROTOCOL_VERSION}"
+            ),
+            ProtocolError::Payload(error) => write!(f, "message payload is invalid: {error}"),
+        }
+    }
+}
+
+// varint encoding keeps small numbers and empty strings down to a single byte
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+/// Serializes message into the version byte followed by its bincode representation.
+/// Peer channels only carry text, so the bytes are sent base64 encoded.
+pub fn encode<T: Serialize>(message: &T) -> String {
+    let mut bytes = vec![PROTOCOL_VERSION];
+    options()
+        .serialize_into(&mut bytes, message)
+        .expect("messages should always be serializable");
+    base64::encode(bytes)
+}
+
+pub fn decode<T: DeserializeOwned>(message: &str) -> Result<T, ProtocolError> {
+    let bytes = base64::decode(message).map_err(ProtocolError::Encoding)?;
+    match bytes.split_first() {
+        None => Err(ProtocolError::Empty),
+        Some((&PROTOCOL_VERSION, payload)) => options()
+            .deserialize(payload)
+            .map_err(ProtocolError::Payload),
+        Some((&version, _)) => Err(ProtocolError::Version(version)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::utils::ClientMessage;
+
+    fn hello() -> ClientMessage {
+        ClientMessage::Hello {
+            reconnect_token: "token".to_string(),
+            nickname: "Zbyszek".to_string(),
+            player_id: Some(3),
+        }
+    }
+
+    #[test]
+    fn message_round_trip() {
+        match decode::<ClientMessage>(&encode(&hello())) {
+            Ok(ClientMessage::Hello {
+                reconnect_token,
+                nickname,
+                player_id,
+            }) => {
+                assert_eq!(reconnect_token, "token");
+                assert_eq!(nickname, "Zbyszek");
+                assert_eq!(player_id, Some(3));
+            }
+            _ => panic!("hello should decode as it was encoded"),
+        }
+    }
+
+    #[test]
+    fn other_protocol_version_is_rejected() {
+        let mut bytes = base64::decode(encode(&hello())).unwrap();
+        bytes[0] = PROTOCOL_VERSION.wrapping_add(1);
+        match decode::<ClientMessage>(&base64::encode(bytes)) {
+            Err(ProtocolError::Version(version)) => {
+                assert_eq!(version, PROTOCOL_VERSION.wrapping_add(1))
+            }
+            _ => panic!("message of another protocol version should be rejected"),
+        }
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        assert!(matches!(
+            decode::<ClientMessage>("not base64!"),
+            Err(ProtocolError::Encoding(_))
+        ));
+        assert!(matches!(
+            decode::<ClientMessage>(""),
+            Err(ProtocolError::Empty)
+        ));
+        assert!(matches!(
+            decode::<ClientMessage>(&base64::encode([PROTOCOL_VERSION, 200])),
+            Err(ProtocolError::Payload(_))
+        ));
+        // the handshake isn't a message
+        assert!(decode::<ClientMessage>(&Handshake::local().encode()).is_err());
+    }
+
+    #[test]
+    fn handshake_round_trip() {
+        let handshake = Handshake::decode(&Handshake::local().encode()).unwrap();
+        assert!(handshake.compatible());
+        assert_eq!(handshake.build_id, BUILD_ID);
+    }
+
+    #[test]
+    fn handshake_of_other_protocol_version_is_incompatible() {
+        let handshake = Handshake::decode(r#"{"protocol_version":1,"build_id":"0.1.0"}"#).unwrap();
+        assert!(!handshake.compatible());
+        assert_eq!(handshake.version(), "0.1.0 (protocol 1)");
+        assert!(Handshake::decode(&encode(&hello())).is_err());
+    }
+}
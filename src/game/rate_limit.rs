@@ -0,0 +1,82 @@
+/// Counts a peer's messages in one second windows, to turn away those beyond a limit.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimit {
+    window_start: f64,
+    count: u32,
+    /// Windows in which the peer went over the limit.
+    pub violations: u32,
+}
+
+impl RateLimit {
+    /// Counts a message received at `now`, in seconds, and tells whether it's within
+    /// `limit` messages per second.
+    pub fn allow(&mut self, now: f64, limit: u32) -> bool {
+        if now - self.window_start >= 1.0 {
+            self.window_start = now;
+            self.count = 0;
+        }
+        self.count = self.count.saturating_add(1);
+        if self.count == limit + 1 {
+            self.violations += 1;
+        }
+        self.count <= limit
+    }
+}
+
+/// Rate limits of a player's messages, inputs and chat apart from the rest.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MessageRates {
+    pub input: RateLimit,
+    pub chat: RateLimit,
+    pub other: RateLimit,
+}
+
+impl MessageRates {
+    /// Windows in which the player went over any of the limits.
+    pub fn violations(&self) -> u32 {
+        self.input.violations + self.chat.violations + self.other.violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_up_to_the_limit_per_window() {
+        let mut rate_limit = RateLimit::default();
+        for i in 0..3 {
+            assert!(rate_limit.allow(10.0 + i as f64 * 0.1, 3));
+        }
+        assert!(!rate_limit.allow(10.5, 3));
+        assert!(!rate_limit.allow(10.9, 3));
+        // a new window starts a second after the previous one did
+        assert!(rate_limit.allow(11.0, 3));
+    }
+
+    #[test]
+    fn counts_a_violation_once_per_window() {
+        let mut rate_limit = RateLimit::default();
+        for _ in 0..10 {
+            rate_limit.allow(10.0, 2);
+        }
+        assert_eq!(rate_limit.violations, 1);
+        for _ in 0..2 {
+            rate_limit.allow(11.0, 2);
+        }
+        assert_eq!(rate_limit.violations, 1);
+        for _ in 0..3 {
+            rate_limit.allow(12.0, 2);
+        }
+        assert_eq!(rate_limit.violations, 2);
+    }
+
+    #[test]
+    fn violations_add_up_across_kinds() {
+        let mut rates = MessageRates::default();
+        rates.input.allow(10.0, 0);
+        rates.chat.allow(10.0, 0);
+        rates.other.allow(10.0, 1);
+        assert_eq!(rates.violations(), 2);
+    }
+}
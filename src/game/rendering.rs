@@ -1,3 +1,5 @@
+use crate::game::latency::Ping;
+use crate::game::shootout::PenaltyStatus;
 use crate::game::utils::{Circle, Edge, Score};
 use std::f64::consts;
 use wasm_bindgen::JsValue;
@@ -103,36 +105,7 @@ pub(crate) fn draw_pitch(
 
 pub(crate) fn draw_goals(ctx: &CanvasRenderingContext2d, goal_posts: &[Circle]) {
     for goal_post in goal_posts {
-        ctx.set_fill_style(&JsValue::from(if goal_post.red {
-            RED_PLAYER_COLOR
-        } else {
-            BLUE_PLAYER_COLOR
-        }));
-        ctx.begin_path();
-        ctx.arc(
-            goal_post.x as f64,
-            goal_post.y as f64,
-            goal_post.radius as f64 - OUTLINE_WIDTH / 2.0,
-            0.0,
-            2.0 * consts::PI,
-        )
-        .unwrap();
-        ctx.close_path();
-        ctx.fill();
-
-        ctx.set_stroke_style(&JsValue::from(OUTLINE_COLOR));
-        ctx.set_line_width(OUTLINE_WIDTH);
-        ctx.begin_path();
-        ctx.arc(
-            goal_post.x as f64,
-            goal_post.y as f64,
-            goal_post.radius as f64 - OUTLINE_WIDTH / 2.0,
-            0.0,
-            2.0 * consts::PI,
-        )
-        .unwrap();
-        ctx.close_path();
-        ctx.stroke();
+        draw_circle(ctx, goal_post, team_color(goal_post.red));
     }
 }
 
@@ -152,38 +125,93 @@ pub(crate) fn draw_score(
     .unwrap();
 }
 
-pub(crate) fn draw_players(ctx: &CanvasRenderingContext2d, players: &[Circle]) {
-    for player in players {
-        ctx.set_fill_style(&JsValue::from(if player.red {
-            RED_PLAYER_COLOR
-        } else {
-            BLUE_PLAYER_COLOR
-        }));
-        ctx.begin_path();
-        ctx.arc(
-            player.x as f64,
-            player.y as f64,
-            player.radius as f64 - OUTLINE_WIDTH / 2.0,
-            0.0,
-            2.0 * consts::PI,
-        )
+pub(crate) fn draw_clock(
+    ctx: &CanvasRenderingContext2d,
+    seconds_left: Option<u32>,
+    overtime: bool,
+    stadium_width: f64,
+    pitch_top_line: f64,
+) {
+    let clock = seconds_left.map(|seconds| format!("{:02}:{:02}", seconds / 60, seconds % 60));
+    let text = match (clock, overtime) {
+        (Some(clock), true) => format!("OT {clock}"),
+        (Some(clock), false) => clock,
+        (None, true) => "Golden goal".to_string(),
+        (None, false) => return,
+    };
+    ctx.set_font("bold 20px arial");
+    ctx.set_fill_style(&JsValue::from(PITCH_LINE_COLOR));
+    ctx.fill_text(&text, stadium_width / 2.0 + 120.0, pitch_top_line / 2.0)
         .unwrap();
-        ctx.close_path();
-        ctx.fill();
+}
+
+pub(crate) fn draw_player_left(
+    ctx: &CanvasRenderingContext2d,
+    red: bool,
+    player_number: i32,
+    stadium_width: f64,
+    pitch_top_line: f64,
+) {
+    let (team, team_color) = if red {
+        ("Red", RED_PLAYER_COLOR)
+    } else {
+        ("Blue", BLUE_PLAYER_COLOR)
+    };
+    ctx.set_font("bold 20px arial");
+    ctx.set_fill_style(&JsValue::from(team_color));
+    ctx.fill_text(
+        &format!("{team} #{player_number} left"),
+        stadium_width / 2.0 - 120.0,
+        pitch_top_line / 2.0,
+    )
+    .unwrap();
+}
+
+pub(crate) fn draw_penalty_status(
+    ctx: &CanvasRenderingContext2d,
+    status: &PenaltyStatus,
+    stadium_width: f64,
+    stadium_height: f64,
+    pitch_bottom_line: f64,
+) {
+    let (team, team_color) = if status.red_kicking {
+        ("Red", RED_PLAYER_COLOR)
+    } else {
+        ("Blue", BLUE_PLAYER_COLOR)
+    };
+    ctx.set_font("bold 20px arial");
+    ctx.set_fill_style(&JsValue::from(PITCH_LINE_COLOR));
+    ctx.fill_text(
+        &format!(
+            "Penalties {} - {}, round {}: {} #{} to kick",
+            status.penalties.red_score,
+            status.penalties.blue_score,
+            status.round,
+            team,
+            status.shooter_number
+        ),
+        stadium_width / 2.0,
+        (stadium_height + pitch_bottom_line) / 2.0,
+    )
+    .unwrap();
 
+    if let Some(scored) = status.last_kick_scored {
+        let text = if scored { "Goal!" } else { "Missed!" };
+        ctx.set_font("bold 42px arial");
+        ctx.set_fill_style(&JsValue::from(team_color));
+        ctx.fill_text(text, stadium_width / 2.0, stadium_height / 2.0)
+            .unwrap();
         ctx.set_stroke_style(&JsValue::from(OUTLINE_COLOR));
-        ctx.set_line_width(OUTLINE_WIDTH);
-        ctx.begin_path();
-        ctx.arc(
-            player.x as f64,
-            player.y as f64,
-            player.radius as f64 - OUTLINE_WIDTH / 2.0,
-            0.0,
-            2.0 * consts::PI,
-        )
-        .unwrap();
-        ctx.close_path();
-        ctx.stroke();
+        ctx.stroke_text(text, stadium_width / 2.0, stadium_height / 2.0)
+            .unwrap();
+    }
+}
+
+pub(crate) fn draw_players(ctx: &CanvasRenderingContext2d, players: &[Circle]) {
+    for player in players {
+        if !draw_circle(ctx, player, team_color(player.red)) {
+            continue;
+        }
 
         // draw number on player
         ctx.set_font("bold 18px arial");
@@ -194,36 +222,72 @@ pub(crate) fn draw_players(ctx: &CanvasRenderingContext2d, players: &[Circle]) {
             player.y as f64,
         )
         .unwrap();
+
+        // draw name tag above player
+        if !player.nickname.is_empty() {
+            ctx.set_font("bold 12px arial");
+            ctx.fill_text(
+                &player.nickname,
+                player.x as f64,
+                player.y as f64 - player.radius as f64 - 8.0,
+            )
+            .unwrap();
+        }
     }
 }
 
 pub(crate) fn draw_ball(ctx: &CanvasRenderingContext2d, ball: &Circle) {
-    ctx.set_fill_style(&JsValue::from(BALL_COLOR));
+    draw_circle(ctx, ball, BALL_COLOR);
+}
+
+fn team_color(red: bool) -> &'static str {
+    if red {
+        RED_PLAYER_COLOR
+    } else {
+        BLUE_PLAYER_COLOR
+    }
+}
+
+/// Fills the circle with given color and outlines it, returning whether it could be drawn.
+/// Circles too small for their outline, as a corrupted replay or a misbehaving host
+/// may send, are skipped.
+fn draw_circle(ctx: &CanvasRenderingContext2d, circle: &Circle, color: &str) -> bool {
+    let radius = circle.radius as f64 - OUTLINE_WIDTH / 2.0;
+    ctx.set_fill_style(&JsValue::from(color));
     ctx.begin_path();
-    ctx.arc(
-        ball.x as f64,
-        ball.y as f64,
-        ball.radius as f64 - OUTLINE_WIDTH / 2.0,
-        0.0,
-        2.0 * consts::PI,
-    )
-    .unwrap();
+    if ctx
+        .arc(
+            circle.x as f64,
+            circle.y as f64,
+            radius,
+            0.0,
+            2.0 * consts::PI,
+        )
+        .is_err()
+    {
+        return false;
+    }
     ctx.close_path();
     ctx.fill();
 
     ctx.set_stroke_style(&JsValue::from(OUTLINE_COLOR));
     ctx.set_line_width(OUTLINE_WIDTH);
     ctx.begin_path();
-    ctx.arc(
-        ball.x as f64,
-        ball.y as f64,
-        ball.radius as f64 - OUTLINE_WIDTH / 2.0,
-        0.0,
-        2.0 * consts::PI,
-    )
-    .unwrap();
+    if ctx
+        .arc(
+            circle.x as f64,
+            circle.y as f64,
+            radius,
+            0.0,
+            2.0 * consts::PI,
+        )
+        .is_err()
+    {
+        return false;
+    }
     ctx.close_path();
     ctx.stroke();
+    true
 }
 
 pub(crate) fn draw_red_scored(
@@ -254,16 +318,104 @@ pub(crate) fn draw_blue_scored(
         .unwrap();
 }
 
+pub(crate) fn draw_rematch_votes(
+    ctx: &CanvasRenderingContext2d,
+    votes: u32,
+    players: u32,
+    stadium_width: f64,
+    stadium_height: f64,
+) {
+    ctx.set_font("bold 20px arial");
+    ctx.set_fill_style(&JsValue::from(TEXT_COLOR));
+    ctx.fill_text(
+        &format!("Rematch votes: {votes}/{players}"),
+        stadium_width / 2.0,
+        stadium_height / 2.0 + 60.0,
+    )
+    .unwrap();
+}
+
+/// Table of everyone in the match with their ping, red team first.
+pub(crate) fn draw_player_list(
+    ctx: &CanvasRenderingContext2d,
+    players: &[(&Circle, Option<Ping>)],
+    stadium_width: f64,
+    stadium_height: f64,
+) {
+    const ROW_HEIGHT: f64 = 24.0;
+    const WIDTH: f64 = 320.0;
+    let mut players = players.to_vec();
+    players.sort_by_key(|(player, _)| (!player.red, player.player_number));
+
+    let height = ROW_HEIGHT * (players.len() + 1) as f64;
+    let left = (stadium_width - WIDTH) / 2.0;
+    let top = (stadium_height - height) / 2.0;
+    ctx.set_global_alpha(0.8);
+    ctx.set_fill_style(&JsValue::from(OUTLINE_COLOR));
+    ctx.fill_rect(left, top, WIDTH, height);
+    ctx.set_global_alpha(1.0);
+
+    ctx.set_font("bold 16px arial");
+    ctx.set_text_align("left");
+    ctx.set_fill_style(&JsValue::from(TEXT_COLOR));
+    ctx.fill_text("Player", left + 12.0, top + ROW_HEIGHT / 2.0)
+        .unwrap();
+    ctx.fill_text("Ping", left + WIDTH * 0.65, top + ROW_HEIGHT / 2.0)
+        .unwrap();
+    for (row, (player, ping)) in players.iter().enumerate() {
+        let y = top + ROW_HEIGHT * (row as f64 + 1.5);
+        ctx.set_fill_style(&JsValue::from(if player.red {
+            RED_PLAYER_COLOR
+        } else {
+            BLUE_PLAYER_COLOR
+        }));
+        ctx.fill_text(
+            &format!("#{} {}", player.player_number, player.nickname),
+            left + 12.0,
+            y,
+        )
+        .unwrap();
+        let ping = match ping {
+            Some(ping) => format!("{} ms ± {}", ping.round_trip, ping.jitter),
+            None => "-".to_string(),
+        };
+        ctx.set_fill_style(&JsValue::from(TEXT_COLOR));
+        ctx.fill_text(&ping, left + WIDTH * 0.65, y).unwrap();
+    }
+    ctx.set_text_align("center");
+}
+
 pub(crate) fn draw_game_ended(
     ctx: &CanvasRenderingContext2d,
     score: &Score,
+    overtime: bool,
+    penalties: Option<&Score>,
     stadium_width: f64,
     stadium_height: f64,
 ) {
     let half_text_height = 21.0;
     ctx.set_font("bold 42px arial");
     ctx.set_stroke_style(&JsValue::from(OUTLINE_COLOR));
-    if score.red_score > score.blue_score {
+    // a level match can still be won on penalties
+    let deciding_score = match penalties {
+        Some(penalties) if score.red_score == score.blue_score => penalties,
+        _ => score,
+    };
+    if deciding_score.red_score == deciding_score.blue_score {
+        ctx.set_fill_style(&JsValue::from(TEXT_COLOR));
+        ctx.fill_text(
+            "Draw!",
+            stadium_width / 2.0,
+            stadium_height / 2.0 - half_text_height,
+        )
+        .unwrap();
+        ctx.stroke_text(
+            "Draw!",
+            stadium_width / 2.0,
+            stadium_height / 2.0 - half_text_height,
+        )
+        .unwrap();
+    } else if deciding_score.red_score > deciding_score.blue_score {
         ctx.set_fill_style(&JsValue::from(RED_PLAYER_COLOR));
         ctx.fill_text(
             "Red Won!",
@@ -292,15 +444,23 @@ pub(crate) fn draw_game_ended(
         )
         .unwrap();
     }
+    let result = match (penalties, overtime) {
+        (Some(penalties), _) => format!(
+            "{} - {} ({} - {} pen.)",
+            score.red_score, score.blue_score, penalties.red_score, penalties.blue_score
+        ),
+        (None, true) => format!("{} - {} (OT)", score.red_score, score.blue_score),
+        (None, false) => format!("{} - {}", score.red_score, score.blue_score),
+    };
     ctx.set_fill_style(&JsValue::from(TEXT_COLOR));
     ctx.fill_text(
-        &format!("{} - {}", score.red_score, score.blue_score),
+        &result,
         stadium_width / 2.0,
         stadium_height / 2.0 + half_text_height,
     )
     .unwrap();
     ctx.stroke_text(
-        &format!("{} - {}", score.red_score, score.blue_score),
+        &result,
         stadium_width / 2.0,
         stadium_height / 2.0 + half_text_height,
     )
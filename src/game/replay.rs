@@ -0,0 +1,444 @@
+use crate::game::constants::{
+    BALL_RADIUS, GOAL_BREADTH, NOTICE_TIME, PITCH_BOTTOM_LINE, PITCH_LEFT_LINE, PITCH_LINE_WIDTH,
+    PITCH_RIGHT_LINE, PITCH_TOP_LINE, RESET_TIME, STADIUM_HEIGHT, STADIUM_WIDTH, TICKS_PER_SECOND,
+};
+use crate::game::rendering;
+use crate::game::shootout::PenaltyStatus;
+use crate::game::utils::{Circle, Edge, Message, Score};
+use crate::utils::now_seconds;
+use serde::{Deserialize, Serialize};
+use web_sys::CanvasRenderingContext2d;
+
+/// Version of the replay file format, bump it on every incompatible change to `Message`.
+pub const REPLAY_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReplayFrame {
+    pub tick: u32,
+    pub message: Message,
+}
+
+impl ReplayFrame {
+    /// Whether everything the frame has drawn is in shape to be drawn.
+    fn is_valid(&self) -> bool {
+        match &self.message {
+            Message::GameInit {
+                edges,
+                goal_posts,
+                players,
+                ball,
+                ..
+            } => {
+                edges.iter().all(Edge::is_valid)
+                    && goal_posts.iter().chain(players).all(Circle::is_valid)
+                    && ball.is_valid()
+            }
+            Message::GameState { players, ball, .. } => {
+                players.iter().all(Circle::is_valid) && ball.is_valid()
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Recording of the messages the host sent to players during a match, those that matter for drawing it.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Replay {
+    pub version: u32,
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Replay::new()
+    }
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Adds the message to the recording, unless it changes nothing that's drawn.
+    pub fn record(&mut self, tick: u32, message: &Message) {
+        if matches!(
+            message,
+            Message::RematchVotes { .. }
+                | Message::Ping { .. }
+                | Message::Migration { .. }
+                | Message::Dropped
+                | Message::Lobby { .. }
+                | Message::MatchStarted
+                | Message::Chat { .. }
+        ) {
+            return;
+        }
+        self.frames.push(ReplayFrame {
+            tick,
+            message: message.clone(),
+        });
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Replay, String> {
+        let replay = serde_json::from_str::<Replay>(json)
+            .map_err(|error| format!("not a valid replay file: {error}"))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!(
+                "unsupported replay version {}, expected {}",
+                replay.version, REPLAY_VERSION
+            ));
+        }
+        // the file may have been edited or damaged, and it's drawn as it is
+        if let Some(frame) = replay.frames.iter().find(|frame| !frame.is_valid()) {
+            return Err(format!("invalid replay frame at tick {}", frame.tick));
+        }
+        if replay
+            .frames
+            .windows(2)
+            .any(|frames| frames[0].tick > frames[1].tick)
+        {
+            return Err("replay frames are out of order".to_string());
+        }
+        Ok(replay)
+    }
+}
+
+/// Match state reconstructed from replay frames, mirroring what a client would display.
+struct ReplayState {
+    edges: Vec<Edge>,
+    goal_posts: Vec<Circle>,
+    players: Vec<Circle>,
+    ball: Circle,
+    score: Score,
+    time_left: Option<u32>,
+    overtime: bool,
+    penalty_status: Option<PenaltyStatus>,
+    // tick of the last goal and whether it was red who scored
+    last_goal: Option<(u32, bool)>,
+    // tick when a player left, with their team and number
+    departure: Option<(u32, bool, i32)>,
+    game_ended: bool,
+}
+
+impl ReplayState {
+    fn new() -> ReplayState {
+        ReplayState {
+            edges: Vec::new(),
+            goal_posts: Vec::new(),
+            players: Vec::new(),
+            ball: Circle::new(0.0, 0.0, BALL_RADIUS, false, -1),
+            score: Score::new(0, 0),
+            time_left: None,
+            overtime: false,
+            penalty_status: None,
+            last_goal: None,
+            departure: None,
+            game_ended: false,
+        }
+    }
+
+    fn apply(&mut self, frame: &ReplayFrame) {
+        match &frame.message {
+            Message::GameInit {
+                edges,
+                goal_posts,
+                players,
+                ball,
+                score,
+                game_ended,
+                ..
+            } => {
+                self.edges = edges.clone();
+                self.goal_posts = goal_posts.clone();
+                self.players = players.clone();
+                self.ball = ball.clone();
+                self.score = score.clone();
+                self.game_ended = *game_ended;
+            }
+            Message::GameState {
+                players,
+                ball,
+                time_left,
+                overtime,
+            } => {
+                self.players = players.clone();
+                self.ball = ball.clone();
+                self.time_left = *time_left;
+                self.overtime = *overtime;
+            }
+            Message::GoalScored { red_scored, score } => {
+                self.score = score.clone();
+                self.last_goal = Some((frame.tick, *red_scored));
+            }
+            Message::GameEnded => {
+                self.game_ended = true;
+            }
+            Message::PenaltyKick {
+                red_kicking,
+                shooter_number,
+                round,
+            } => {
+                self.penalty_status = Some(PenaltyStatus::next_kick(
+                    self.penalty_status.as_ref(),
+                    *red_kicking,
+                    *shooter_number,
+                    *round,
+                ));
+            }
+            Message::PenaltyTaken {
+                red_kicking,
+                scored,
+                penalties,
+            } => {
+                if let Some(status) = self.penalty_status.as_mut() {
+                    status.kick_taken(*red_kicking, *scored, penalties.clone());
+                }
+            }
+            Message::PlayerLeft { red, player_number } => {
+                self.departure = Some((frame.tick, *red, *player_number));
+            }
+            // replays hold every state in full, as `GameState`
+            Message::Keyframe { .. } | Message::StateDelta { .. } => {}
+            Message::RematchVotes { .. }
+            | Message::Ping { .. }
+            | Message::Migration { .. }
+            | Message::Dropped
+            | Message::Lobby { .. }
+            | Message::MatchStarted
+            | Message::Chat { .. } => {}
+            Message::Rematch => {
+                self.score = Score::new(0, 0);
+                self.time_left = None;
+                self.overtime = false;
+                self.penalty_status = None;
+                self.last_goal = None;
+                self.departure = None;
+                self.game_ended = false;
+            }
+        }
+    }
+}
+
+/// Plays a recorded match back on a canvas, with pausing, seeking and changing speed.
+pub struct ReplayViewer {
+    replay: Replay,
+    state: ReplayState,
+    // index of the first frame not yet applied to `state`
+    next_frame: usize,
+    // playback position, in ticks
+    position: f64,
+    speed: f64,
+    paused: bool,
+    last_tick_time: Option<f64>,
+    context: CanvasRenderingContext2d,
+}
+
+impl ReplayViewer {
+    pub fn new(replay: Replay, context: CanvasRenderingContext2d) -> ReplayViewer {
+        let mut viewer = ReplayViewer {
+            replay,
+            state: ReplayState::new(),
+            next_frame: 0,
+            position: 0.0,
+            speed: 1.0,
+            paused: false,
+            last_tick_time: None,
+            context,
+        };
+        viewer.seek(viewer.first_tick());
+        viewer
+    }
+
+    /// Advances playback by the time elapsed since the previous tick and draws the current frame.
+    pub fn tick(&mut self) {
+        let now = now_seconds();
+        let elapsed = self.last_tick_time.map_or(0.0, |last| now - last);
+        self.last_tick_time = Some(now);
+
+        if !self.paused {
+            let position = self.position + elapsed * TICKS_PER_SECOND as f64 * self.speed;
+            if position >= self.last_tick() as f64 {
+                self.paused = true;
+            }
+            self.seek_exact(position);
+        }
+        self.draw();
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused && self.current_tick() >= self.last_tick() {
+            self.seek(self.first_tick());
+        }
+        self.paused = !self.paused;
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    pub fn seek(&mut self, tick: u32) {
+        self.seek_exact(tick as f64);
+    }
+
+    /// Pauses playback and moves to the next recorded frame.
+    pub fn step_forward(&mut self) {
+        self.paused = true;
+        let current_tick = self.current_tick();
+        if let Some(frame) = self
+            .replay
+            .frames
+            .iter()
+            .find(|frame| frame.tick > current_tick)
+        {
+            self.seek(frame.tick);
+        }
+    }
+
+    /// Pauses playback and moves to the previous recorded frame.
+    pub fn step_backward(&mut self) {
+        self.paused = true;
+        let current_tick = self.current_tick();
+        if let Some(frame) = self
+            .replay
+            .frames
+            .iter()
+            .rev()
+            .find(|frame| frame.tick < current_tick)
+        {
+            self.seek(frame.tick);
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn current_tick(&self) -> u32 {
+        self.position as u32
+    }
+
+    pub fn first_tick(&self) -> u32 {
+        self.replay.frames.first().map_or(0, |frame| frame.tick)
+    }
+
+    pub fn last_tick(&self) -> u32 {
+        self.replay.frames.last().map_or(0, |frame| frame.tick)
+    }
+
+    fn seek_exact(&mut self, position: f64) {
+        let position = position.clamp(self.first_tick() as f64, self.last_tick() as f64);
+        if position < self.position {
+            // frames only describe changes, so going back requires replaying from the start
+            self.state = ReplayState::new();
+            self.next_frame = 0;
+        }
+        self.position = position;
+
+        let current_tick = self.current_tick();
+        while let Some(frame) = self.replay.frames.get(self.next_frame) {
+            if frame.tick > current_tick {
+                break;
+            }
+            self.state.apply(frame);
+            self.next_frame += 1;
+        }
+    }
+
+    pub fn draw(&self) {
+        let state = &self.state;
+        rendering::draw_stadium(&self.context, STADIUM_WIDTH as f64, STADIUM_HEIGHT as f64);
+        rendering::draw_pitch(
+            &self.context,
+            &state.edges,
+            PITCH_LEFT_LINE as f64,
+            PITCH_RIGHT_LINE as f64,
+            PITCH_TOP_LINE as f64,
+            PITCH_BOTTOM_LINE as f64,
+            PITCH_LINE_WIDTH as f64,
+            STADIUM_WIDTH as f64,
+            STADIUM_HEIGHT as f64,
+            GOAL_BREADTH as f64,
+        );
+        rendering::draw_goals(&self.context, &state.goal_posts);
+        rendering::draw_score(
+            &self.context,
+            &state.score,
+            STADIUM_WIDTH as f64,
+            PITCH_TOP_LINE as f64,
+        );
+        if state.time_left.is_some() || state.overtime {
+            rendering::draw_clock(
+                &self.context,
+                state
+                    .time_left
+                    .map(|time_left| time_left / TICKS_PER_SECOND),
+                state.overtime,
+                STADIUM_WIDTH as f64,
+                PITCH_TOP_LINE as f64,
+            );
+        }
+        rendering::draw_players(&self.context, &state.players);
+        rendering::draw_ball(&self.context, &state.ball);
+        if let Some((goal_tick, red_scored)) = state.last_goal {
+            if self.current_tick() < goal_tick.saturating_add(RESET_TIME) {
+                if red_scored {
+                    rendering::draw_red_scored(
+                        &self.context,
+                        STADIUM_WIDTH as f64,
+                        STADIUM_HEIGHT as f64,
+                    );
+                } else {
+                    rendering::draw_blue_scored(
+                        &self.context,
+                        STADIUM_WIDTH as f64,
+                        STADIUM_HEIGHT as f64,
+                    );
+                }
+            }
+        }
+        if let Some((tick, red, player_number)) = state.departure {
+            if self.current_tick() < tick.saturating_add(NOTICE_TIME) {
+                rendering::draw_player_left(
+                    &self.context,
+                    red,
+                    player_number,
+                    STADIUM_WIDTH as f64,
+                    PITCH_TOP_LINE as f64,
+                );
+            }
+        }
+        if let Some(status) = state.penalty_status.as_ref() {
+            rendering::draw_penalty_status(
+                &self.context,
+                status,
+                STADIUM_WIDTH as f64,
+                STADIUM_HEIGHT as f64,
+                PITCH_BOTTOM_LINE as f64,
+            );
+        }
+        if state.game_ended {
+            rendering::draw_game_ended(
+                &self.context,
+                &state.score,
+                state.overtime,
+                state
+                    .penalty_status
+                    .as_ref()
+                    .map(|status| &status.penalties),
+                STADIUM_WIDTH as f64,
+                STADIUM_HEIGHT as f64,
+            );
+        }
+    }
+}
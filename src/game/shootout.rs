@@ -0,0 +1,204 @@
+use crate::game::constants::{PENALTY_RESULT_TIME, PENALTY_ROUNDS};
+use crate::game::utils::Score;
+use rapier2d::dynamics::RigidBodyHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PenaltyPhase {
+    /// Players and the ball have to be placed for the next kick.
+    Setup,
+    /// Shooter is taking the kick, value is the number of ticks left for scoring.
+    Kicking(u32),
+    /// Kick is over and its result is shown, value is the number of ticks left.
+    Result(u32),
+}
+
+/// Progress of a penalty shootout, as shown to players.
+#[derive(Debug, Clone)]
+pub struct PenaltyStatus {
+    pub red_kicking: bool,
+    pub shooter_number: i32,
+    pub round: u32,
+    pub penalties: Score,
+    /// Result of the kick that has just been taken, if it's still being shown.
+    pub last_kick_scored: Option<bool>,
+}
+
+impl PenaltyStatus {
+    /// Status announcing the next kick, keeping the score of the previous ones.
+    pub fn next_kick(
+        previous: Option<&PenaltyStatus>,
+        red_kicking: bool,
+        shooter_number: i32,
+        round: u32,
+    ) -> PenaltyStatus {
+        PenaltyStatus {
+            red_kicking,
+            shooter_number,
+            round,
+            penalties: previous.map_or_else(|| Score::new(0, 0), |status| status.penalties.clone()),
+            last_kick_scored: None,
+        }
+    }
+
+    pub fn kick_taken(&mut self, red_kicking: bool, scored: bool, penalties: Score) {
+        self.red_kicking = red_kicking;
+        self.penalties = penalties;
+        self.last_kick_scored = Some(scored);
+    }
+}
+
+/// Penalty shootout settling a drawn match.
+/// Teams take turns, red first, and after `PENALTY_ROUNDS` each it goes to sudden death.
+pub struct Shootout {
+    pub red_kicking: bool,
+    pub phase: PenaltyPhase,
+    pub shooter: Option<RigidBodyHandle>,
+    pub goalkeeper: Option<RigidBodyHandle>,
+    pub shooter_number: i32,
+    red_kicks: u32,
+    blue_kicks: u32,
+    red_goals: u32,
+    blue_goals: u32,
+    last_kick_scored: bool,
+}
+
+impl Default for Shootout {
+    fn default() -> Self {
+        Shootout::new()
+    }
+}
+
+impl Shootout {
+    pub fn new() -> Shootout {
+        Shootout {
+            red_kicking: true,
+            phase: PenaltyPhase::Setup,
+            shooter: None,
+            goalkeeper: None,
+            shooter_number: -1,
+            red_kicks: 0,
+            blue_kicks: 0,
+            red_goals: 0,
+            blue_goals: 0,
+            last_kick_scored: false,
+        }
+    }
+
+    /// Number of kicks the currently kicking team has already taken.
+    pub fn kicks_taken(&self) -> u32 {
+        if self.red_kicking {
+            self.red_kicks
+        } else {
+            self.blue_kicks
+        }
+    }
+
+    /// Whether the player owning given body may move during the current kick.
+    pub fn can_move(&self, rigid_body_handle: RigidBodyHandle) -> bool {
+        self.shooter == Some(rigid_body_handle) || self.goalkeeper == Some(rigid_body_handle)
+    }
+
+    pub fn record_kick(&mut self, scored: bool) {
+        if self.red_kicking {
+            self.red_kicks += 1;
+            self.red_goals += scored as u32;
+        } else {
+            self.blue_kicks += 1;
+            self.blue_goals += scored as u32;
+        }
+        self.last_kick_scored = scored;
+        self.phase = PenaltyPhase::Result(PENALTY_RESULT_TIME);
+    }
+
+    pub fn next_kick(&mut self) {
+        self.red_kicking = !self.red_kicking;
+        self.shooter = None;
+        self.goalkeeper = None;
+        self.phase = PenaltyPhase::Setup;
+    }
+
+    /// Whether one of the teams has won, i.e. the other can no longer catch up.
+    pub fn decided(&self) -> bool {
+        if self.red_kicks <= PENALTY_ROUNDS && self.blue_kicks <= PENALTY_ROUNDS {
+            let red_remaining = PENALTY_ROUNDS - self.red_kicks;
+            let blue_remaining = PENALTY_ROUNDS - self.blue_kicks;
+            if self.red_goals > self.blue_goals + blue_remaining
+                || self.blue_goals > self.red_goals + red_remaining
+            {
+                return true;
+            }
+        }
+        // sudden death is only decided after both teams took the same number of kicks
+        self.red_kicks >= PENALTY_ROUNDS
+            && self.red_kicks == self.blue_kicks
+            && self.red_goals != self.blue_goals
+    }
+
+    pub fn penalties(&self) -> Score {
+        Score::new(self.red_goals, self.blue_goals)
+    }
+
+    pub fn status(&self) -> PenaltyStatus {
+        PenaltyStatus {
+            red_kicking: self.red_kicking,
+            shooter_number: self.shooter_number,
+            round: match self.phase {
+                PenaltyPhase::Result(_) => self.kicks_taken(),
+                _ => self.kicks_taken() + 1,
+            },
+            penalties: self.penalties(),
+            last_kick_scored: match self.phase {
+                PenaltyPhase::Result(_) => Some(self.last_kick_scored),
+                _ => None,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shootout after given kicks, taken in turns with red first.
+    fn shootout(kicks: &[bool]) -> Shootout {
+        let mut shootout = Shootout::new();
+        for &scored in kicks {
+            shootout.record_kick(scored);
+            shootout.next_kick();
+        }
+        shootout
+    }
+
+    #[test]
+    fn undecided_while_both_teams_can_still_win() {
+        assert!(!shootout(&[]).decided());
+        assert!(!shootout(&[true, false]).decided());
+        assert!(!shootout(&[true, false, true, false]).decided());
+    }
+
+    #[test]
+    fn decided_once_a_team_cannot_catch_up() {
+        // red scores 3 of 3, blue misses 3 of 3 and has 2 kicks left
+        let kicks = [true, false, true, false, true, false];
+        assert!(!shootout(&kicks[..5]).decided());
+        assert!(shootout(&kicks).decided());
+        // blue scores 3 of 3 and red misses 4 of 4, before blue's fourth kick
+        let kicks = [false, true, false, true, false, true, false];
+        assert!(shootout(&kicks).decided());
+    }
+
+    #[test]
+    fn level_after_regular_rounds_goes_to_sudden_death() {
+        let mut kicks = vec![true; 2 * PENALTY_ROUNDS as usize];
+        assert!(!shootout(&kicks).decided());
+        // red scoring first doesn't decide it before blue kicks
+        kicks.push(true);
+        assert!(!shootout(&kicks).decided());
+        kicks.push(true);
+        assert!(!shootout(&kicks).decided());
+        kicks.push(false);
+        assert!(!shootout(&kicks).decided());
+        kicks.push(true);
+        assert!(shootout(&kicks).decided());
+    }
+}
@@ -0,0 +1,1240 @@
+use crate::game::constants::{
+    BALL_GROUP, BALL_RADIUS, BALL_TOP_SPEED, BLUE_KICKOFF_GROUP, CENTRE_CIRCLE_RADIUS,
+    FIXED_TIMESTEP, GOAL_BREADTH, GOAL_DEPTH, GOAL_POSTS_GROUP, KICKOFF_BALL_DISTANCE,
+    PENALTY_KICK_TIME, PENALTY_SPOT_DISTANCE, PITCH_BOTTOM_LINE, PITCH_HEIGHT, PITCH_LEFT_LINE,
+    PITCH_LINES_GROUP, PITCH_LINE_HEIGHT, PITCH_LINE_WIDTH, PITCH_RIGHT_LINE, PITCH_TOP_LINE,
+    PITCH_VERTICAL_LINE_HEIGHT, PITCH_WIDTH, PLAYERS_GROUP, PLAYER_ACCELERATION, PLAYER_DIAMETER,
+    PLAYER_RADIUS, PLAYER_TOP_SPEED, RED_KICKOFF_GROUP, RESET_TIME, SHOOTING_DISTANCE,
+    STADIUM_HEIGHT, STADIUM_WALLS_GROUP, STADIUM_WIDTH,
+};
+use crate::game::formations::kickoff_position;
+use crate::game::input::PlayerInput;
+use crate::game::migration::{BodyMotion, MigratingPlayer, MigrationSnapshot};
+use crate::game::shootout::{PenaltyPhase, PenaltyStatus};
+use crate::game::utils::{
+    sanitize_nickname, Arbiter, Circle, Edge, MatchSettings, Player, PlayerBody, Score,
+};
+use rapier2d::dynamics::{
+    CCDSolver, IntegrationParameters, IslandManager, JointSet, RigidBody, RigidBodyBuilder,
+    RigidBodyHandle, RigidBodySet,
+};
+use rapier2d::geometry::{
+    BroadPhase, ColliderBuilder, ColliderHandle, ColliderSet, InteractionGroups, NarrowPhase,
+};
+use rapier2d::pipeline::PhysicsPipeline;
+use rapier2d::prelude::*;
+use std::collections::HashMap;
+use wasm_peers::UserId;
+
+/// Something that happened during a single simulation step
+/// and that the connected players should be told about.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SimulationEvent {
+    GoalScored {
+        red_scored: bool,
+    },
+    GameEnded,
+    PenaltyKick {
+        red_kicking: bool,
+        shooter_number: i32,
+        round: u32,
+    },
+    PenaltyTaken {
+        red_kicking: bool,
+        scored: bool,
+    },
+}
+
+/// State of the match after a single simulation step.
+pub struct Snapshot {
+    pub tick: u32,
+    pub players: Vec<Circle>,
+    /// Stable id of each of `players`, in the same order.
+    pub player_ids: Vec<u32>,
+    pub ball: Circle,
+    pub score: Score,
+    pub red_scored: bool,
+    pub blue_scored: bool,
+    pub game_ended: bool,
+    pub time_left: Option<u32>,
+    pub overtime: bool,
+    /// Team taking the kickoff in progress, true for red.
+    pub kickoff: Option<bool>,
+    pub penalty_status: Option<PenaltyStatus>,
+    pub events: Vec<SimulationEvent>,
+}
+
+/// Browser-free football match: physics world, players, ball and the rules.
+///
+/// It knows nothing about networking nor rendering,
+/// so it can be driven and inspected from native code as well.
+pub struct Simulation {
+    host_player: Option<Player>,
+    players: HashMap<UserId, Player>,
+    edges: Vec<Edge>,
+    goal_posts: Vec<Circle>,
+    ball_body_handle: RigidBodyHandle,
+    // colliders keeping a team away from the ball during the other team's kickoff,
+    // with the team they block, true for red
+    kickoff_barriers: Vec<(ColliderHandle, bool)>,
+    settings: MatchSettings,
+    arbiter: Arbiter,
+    events: Vec<SimulationEvent>,
+    tick: u32,
+    // id given to the next player created, never reused
+    next_player_id: u32,
+
+    // stuff required by physics engine
+    rigid_body_set: RigidBodySet,
+    collider_set: ColliderSet,
+    integration_parameters: IntegrationParameters,
+    physics_pipeline: PhysicsPipeline,
+    island_manager: IslandManager,
+    broad_phase: BroadPhase,
+    narrow_phase: NarrowPhase,
+    joint_set: JointSet,
+    ccd_solver: CCDSolver,
+    physics_hooks: (),
+    event_handler: (),
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation::new(MatchSettings::default())
+    }
+}
+
+impl Simulation {
+    pub fn new(settings: MatchSettings) -> Simulation {
+        let mut rigid_body_set = RigidBodySet::new();
+        let mut collider_set = ColliderSet::new();
+
+        let edges = Simulation::create_pitch_lines(&mut collider_set);
+        let goal_posts = Simulation::create_goals_posts(&mut collider_set);
+        Simulation::create_stadium_walls(&mut collider_set);
+        let kickoff_barriers = Simulation::create_kickoff_barriers(&mut collider_set);
+
+        let ball_body_handle = Simulation::create_ball(&mut rigid_body_set, &mut collider_set);
+
+        let integration_parameters = IntegrationParameters {
+            dt: FIXED_TIMESTEP,
+            ..IntegrationParameters::default()
+        };
+
+        let mut simulation = Simulation {
+            host_player: None,
+            players: HashMap::new(),
+            edges,
+            goal_posts,
+            ball_body_handle,
+            kickoff_barriers,
+            settings,
+            arbiter: Arbiter::new(settings),
+            events: Vec::new(),
+            tick: 0,
+            next_player_id: 0,
+            rigid_body_set,
+            collider_set,
+            integration_parameters,
+            physics_pipeline: PhysicsPipeline::new(),
+            island_manager: IslandManager::new(),
+            broad_phase: BroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            joint_set: JointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            physics_hooks: (),
+            event_handler: (),
+        };
+        simulation.update_kickoff_barriers();
+        simulation
+    }
+
+    /// Advances the match by a single physics step of `FIXED_TIMESTEP` seconds using given inputs.
+    /// Players missing from `inputs` keep their previous input.
+    pub fn step(
+        &mut self,
+        host_input: PlayerInput,
+        inputs: &HashMap<UserId, PlayerInput>,
+    ) -> Snapshot {
+        self.tick += 1;
+        self.check_timer();
+        self.check_kickoff();
+        if let Some(host_player) = self.host_player.as_mut() {
+            host_player.set_input(host_input);
+        }
+        for (user_id, input) in inputs {
+            if let Some(player) = self.players.get_mut(user_id) {
+                player.set_input(*input);
+            }
+        }
+        self.parse_input();
+
+        Simulation::limit_speed(
+            &mut self.rigid_body_set[self.ball_body_handle],
+            BALL_TOP_SPEED,
+        );
+
+        self.physics_pipeline.step(
+            &vector![0.0, 0.0],
+            &self.integration_parameters,
+            &mut self.island_manager,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            &mut self.joint_set,
+            &mut self.ccd_solver,
+            &self.physics_hooks,
+            &self.event_handler,
+        );
+
+        self.snapshot()
+    }
+
+    /// Starts the match over with the same players and settings, keeping the tick count going.
+    pub fn restart(&mut self) {
+        self.arbiter = Arbiter::new(self.settings);
+        self.events.clear();
+        self.reset_game();
+    }
+
+    /// Current state of the match, draining events gathered since the last call.
+    pub fn snapshot(&mut self) -> Snapshot {
+        Snapshot {
+            tick: self.tick,
+            players: self.get_player_entities(),
+            player_ids: self.get_player_ids(),
+            ball: self.get_ball_entity(),
+            score: self.get_score(),
+            red_scored: self.arbiter.red_scored,
+            blue_scored: self.arbiter.blue_scored,
+            game_ended: self.arbiter.game_ended,
+            time_left: self.arbiter.time_left,
+            overtime: self.arbiter.overtime,
+            kickoff: self.arbiter.kickoff,
+            penalty_status: self
+                .arbiter
+                .shootout
+                .as_ref()
+                .map(|shootout| shootout.status()),
+            events: std::mem::take(&mut self.events),
+        }
+    }
+
+    /// Match carried on from the snapshot of a host who left, with `host_id` as the new host.
+    /// Other players come back as they reconnect, see `restore_migrated_player`.
+    pub fn from_migration(snapshot: &MigrationSnapshot, host_id: Option<u32>) -> Simulation {
+        let mut simulation = Simulation::new(snapshot.settings);
+        simulation.tick = snapshot.tick;
+        simulation.next_player_id = snapshot
+            .players
+            .iter()
+            .map(|player| player.id)
+            .max()
+            .unwrap_or(0);
+        simulation.arbiter.red_score = snapshot.score.red_score;
+        simulation.arbiter.blue_score = snapshot.score.blue_score;
+        simulation.arbiter.time_left = snapshot.time_left;
+        simulation.arbiter.overtime = snapshot.overtime;
+        simulation.arbiter.game_ended = snapshot.game_ended;
+        simulation.set_kickoff(None);
+
+        let ball_body = &mut simulation.rigid_body_set[simulation.ball_body_handle];
+        ball_body.set_position(
+            Isometry::new(vector![snapshot.ball.x, snapshot.ball.y], 0.0),
+            true,
+        );
+        ball_body.set_linvel(vector![snapshot.ball.x_speed, snapshot.ball.y_speed], true);
+
+        let host_player = snapshot
+            .players
+            .iter()
+            .find(|player| Some(player.id) == host_id);
+        if let Some(host_player) = host_player {
+            simulation.host_player = Some(simulation.create_migrated_player(host_player));
+        }
+        simulation
+    }
+
+    /// Gives a user reconnecting after a host migration the player they had.
+    pub fn restore_migrated_player(&mut self, user_id: UserId, player: &MigratingPlayer) {
+        let player = self.create_migrated_player(player);
+        self.players.insert(user_id, player);
+    }
+
+    /// State of the match for another player to carry on with, should the host leave.
+    pub fn migration_snapshot(
+        &self,
+        session_id: &str,
+        game_started: bool,
+        succession: Vec<u32>,
+    ) -> MigrationSnapshot {
+        let players = self
+            .players
+            .values()
+            .chain(self.host_player.iter())
+            .map(|player| MigratingPlayer {
+                id: player.id,
+                red: player.red,
+                number: player.number,
+                nickname: player.nickname.clone(),
+                motion: self.body_motion(player.rigid_body_handle),
+            })
+            .collect();
+        MigrationSnapshot {
+            session_id: session_id.to_string(),
+            settings: self.settings,
+            game_started,
+            game_ended: self.arbiter.game_ended,
+            tick: self.tick,
+            score: self.get_score(),
+            time_left: self.arbiter.time_left,
+            overtime: self.arbiter.overtime,
+            ball: self.body_motion(self.ball_body_handle),
+            players,
+            succession,
+        }
+    }
+
+    fn body_motion(&self, body_handle: RigidBodyHandle) -> BodyMotion {
+        let body = &self.rigid_body_set[body_handle];
+        BodyMotion {
+            x: body.translation().x,
+            y: body.translation().y,
+            x_speed: body.linvel().x,
+            y_speed: body.linvel().y,
+        }
+    }
+
+    fn create_migrated_player(&mut self, player: &MigratingPlayer) -> Player {
+        let motion = player.motion;
+        let player_body_handle = Simulation::create_player_body(
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            motion.x,
+            motion.y,
+            player.red,
+        );
+        self.rigid_body_set[player_body_handle]
+            .set_linvel(vector![motion.x_speed, motion.y_speed], true);
+        Player::new(
+            player_body_handle,
+            player.id,
+            PLAYER_RADIUS,
+            player.red,
+            player.number,
+            player.nickname.clone(),
+        )
+    }
+
+    pub fn has_host_player(&self) -> bool {
+        self.host_player.is_some()
+    }
+
+    pub fn add_host_player(&mut self, nickname: &str) {
+        let (x, y) = kickoff_position(true, 0, 1);
+        let host_player = self.create_player(x, y, true, 1, nickname);
+        self.host_player = Some(host_player);
+    }
+
+    /// Creates a player for a newly connected user, placing them in the smaller team.
+    /// They take the last slot of their team's formation, teammates keep their positions.
+    pub fn add_player(&mut self, user_id: UserId, nickname: &str) {
+        let red_players_count = self.host_player.iter().filter(|player| player.red).count()
+            + self.players.values().filter(|player| player.red).count();
+        let blue_players_count = self.players.values().filter(|player| !player.red).count();
+
+        let (red, team_size) = if red_players_count < blue_players_count {
+            (true, red_players_count)
+        } else {
+            (false, blue_players_count)
+        };
+        let (x, y) = kickoff_position(red, team_size, team_size + 1);
+        let player = self.create_player(x, y, red, team_size + 1, nickname);
+        self.players.insert(user_id, player);
+    }
+
+    /// Hands the player of a user over to their new connection.
+    pub fn reassign_player(&mut self, from: &UserId, to: UserId) {
+        if let Some(player) = self.players.remove(from) {
+            self.players.insert(to, player);
+        }
+    }
+
+    /// Brings back a player who left recently, in their old team
+    /// and with their old number, unless someone has taken it in the meantime.
+    pub fn restore_player(&mut self, user_id: UserId, red: bool, number: usize, nickname: &str) {
+        let team_numbers: Vec<usize> = self
+            .players
+            .values()
+            .chain(self.host_player.iter())
+            .filter(|player| player.red == red)
+            .map(|player| player.number)
+            .collect();
+        let number = if team_numbers.contains(&number) {
+            team_numbers.iter().max().map_or(1, |max| max + 1)
+        } else {
+            number
+        };
+        let team_size = team_numbers.len();
+        let (x, y) = kickoff_position(red, team_size, team_size + 1);
+        let player = self.create_player(x, y, red, number, nickname);
+        self.players.insert(user_id, player);
+    }
+
+    /// Removes the player of a user who left, along with their body, and evens out the teams.
+    /// Returns the team and number the player had, if they were in the match.
+    pub fn remove_player(&mut self, user_id: &UserId) -> Option<(bool, usize)> {
+        let player = self.players.remove(user_id)?;
+        self.rigid_body_set.remove(
+            player.rigid_body_handle,
+            &mut self.island_manager,
+            &mut self.collider_set,
+            &mut self.joint_set,
+        );
+        self.rebalance_teams();
+        Some((player.red, player.number))
+    }
+
+    /// Moves the highest numbered player of a team that is two or more players bigger
+    /// to the other one. Host never switches teams.
+    fn rebalance_teams(&mut self) {
+        let red_players_count = self
+            .players
+            .values()
+            .chain(self.host_player.iter())
+            .filter(|player| player.red)
+            .count();
+        let blue_players_count =
+            self.players.len() + self.host_player.iter().count() - red_players_count;
+        let to_red = if blue_players_count > red_players_count + 1 {
+            true
+        } else if red_players_count > blue_players_count + 1 {
+            false
+        } else {
+            return;
+        };
+
+        let new_number = self
+            .players
+            .values()
+            .chain(self.host_player.iter())
+            .filter(|player| player.red == to_red)
+            .map(|player| player.number)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let team_size = if to_red {
+            red_players_count
+        } else {
+            blue_players_count
+        };
+        let player = match self
+            .players
+            .values_mut()
+            .filter(|player| player.red != to_red)
+            .max_by_key(|player| player.number)
+        {
+            Some(player) => player,
+            None => return,
+        };
+        player.red = to_red;
+        player.number = new_number;
+        let groups = Simulation::player_collision_groups(to_red);
+        for &collider_handle in self.rigid_body_set[player.rigid_body_handle].colliders() {
+            self.collider_set[collider_handle].set_collision_groups(groups);
+        }
+        let (x, y) = kickoff_position(to_red, team_size, team_size + 1);
+        player.set_position(&mut self.rigid_body_set, x, y);
+    }
+
+    fn create_pitch_lines(collider_set: &mut ColliderSet) -> Vec<Edge> {
+        let mut edges = Vec::new();
+        let mut create_line_closure = |width, height, x, y, white, membership, filter| {
+            let cuboid_collider = ColliderBuilder::cuboid(width / 2.0, height / 2.0)
+                .collision_groups(InteractionGroups::new(membership, filter))
+                .translation(vector![x, y])
+                .build();
+            edges.push(Edge::new(
+                cuboid_collider.translation().x,
+                cuboid_collider.translation().y,
+                width,
+                height,
+                white,
+            ));
+            collider_set.insert(cuboid_collider);
+        };
+
+        // left higher pitch line
+        create_line_closure(
+            PITCH_LINE_WIDTH,
+            PITCH_VERTICAL_LINE_HEIGHT,
+            PITCH_LEFT_LINE,
+            (STADIUM_HEIGHT - GOAL_BREADTH - PITCH_VERTICAL_LINE_HEIGHT) / 2.0,
+            true,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+        // left lower pitch line
+        create_line_closure(
+            PITCH_LINE_WIDTH,
+            PITCH_VERTICAL_LINE_HEIGHT,
+            PITCH_LEFT_LINE,
+            (STADIUM_HEIGHT + GOAL_BREADTH + PITCH_VERTICAL_LINE_HEIGHT) / 2.0,
+            true,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+        // left goal
+        create_line_closure(
+            PITCH_LINE_WIDTH,
+            GOAL_BREADTH,
+            PITCH_LEFT_LINE - GOAL_DEPTH,
+            STADIUM_HEIGHT / 2.0,
+            false,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+        create_line_closure(
+            GOAL_DEPTH,
+            PITCH_LINE_HEIGHT,
+            PITCH_LEFT_LINE - GOAL_DEPTH / 2.0,
+            (STADIUM_HEIGHT - GOAL_BREADTH) / 2.0,
+            false,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+        create_line_closure(
+            GOAL_DEPTH,
+            PITCH_LINE_HEIGHT,
+            PITCH_LEFT_LINE - GOAL_DEPTH / 2.0,
+            (STADIUM_HEIGHT + GOAL_BREADTH) / 2.0,
+            false,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+
+        // right higher pitch line
+        create_line_closure(
+            PITCH_LINE_WIDTH,
+            PITCH_VERTICAL_LINE_HEIGHT,
+            PITCH_RIGHT_LINE,
+            (STADIUM_HEIGHT - GOAL_BREADTH - PITCH_VERTICAL_LINE_HEIGHT) / 2.0,
+            true,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+        // right lower pitch line
+        create_line_closure(
+            PITCH_LINE_WIDTH,
+            PITCH_VERTICAL_LINE_HEIGHT,
+            PITCH_RIGHT_LINE,
+            (STADIUM_HEIGHT + GOAL_BREADTH + PITCH_VERTICAL_LINE_HEIGHT) / 2.0,
+            true,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+        // right goal
+        create_line_closure(
+            PITCH_LINE_WIDTH,
+            GOAL_BREADTH,
+            PITCH_RIGHT_LINE + GOAL_DEPTH,
+            STADIUM_HEIGHT / 2.0,
+            false,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+        create_line_closure(
+            GOAL_DEPTH,
+            PITCH_LINE_HEIGHT,
+            PITCH_RIGHT_LINE + GOAL_DEPTH / 2.0,
+            (STADIUM_HEIGHT - GOAL_BREADTH) / 2.0,
+            false,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+        create_line_closure(
+            GOAL_DEPTH,
+            PITCH_LINE_HEIGHT,
+            PITCH_RIGHT_LINE + GOAL_DEPTH / 2.0,
+            (STADIUM_HEIGHT + GOAL_BREADTH) / 2.0,
+            false,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+
+        // top pitch line`
+        create_line_closure(
+            PITCH_WIDTH,
+            PITCH_LINE_HEIGHT,
+            STADIUM_WIDTH / 2.0,
+            PITCH_TOP_LINE,
+            true,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+
+        // bottom pitch line
+        create_line_closure(
+            PITCH_WIDTH,
+            PITCH_LINE_HEIGHT,
+            STADIUM_WIDTH / 2.0,
+            PITCH_BOTTOM_LINE,
+            true,
+            PITCH_LINES_GROUP,
+            PITCH_LINES_GROUP,
+        );
+
+        edges
+    }
+
+    pub(crate) fn create_goals_posts(collider_set: &mut ColliderSet) -> Vec<Circle> {
+        let mut goal_posts = Vec::new();
+
+        let mut create_post_closure = |x, y, red| {
+            let ball_collider = ColliderBuilder::ball(BALL_RADIUS)
+                .collision_groups(InteractionGroups::new(GOAL_POSTS_GROUP, GOAL_POSTS_GROUP))
+                .translation(vector![x, y])
+                .build();
+            goal_posts.push(Circle::new(
+                ball_collider.translation().x,
+                ball_collider.translation().y,
+                BALL_RADIUS,
+                red,
+                -1,
+            ));
+            collider_set.insert(ball_collider);
+        };
+        // left red goal
+        create_post_closure(
+            PITCH_LEFT_LINE,
+            PITCH_TOP_LINE + PITCH_HEIGHT / 2.0 - GOAL_BREADTH / 2.0,
+            true,
+        );
+        create_post_closure(
+            PITCH_LEFT_LINE,
+            PITCH_TOP_LINE + PITCH_HEIGHT / 2.0 + GOAL_BREADTH / 2.0,
+            true,
+        );
+
+        // right blue goal
+        create_post_closure(
+            PITCH_RIGHT_LINE,
+            PITCH_TOP_LINE + PITCH_HEIGHT / 2.0 - GOAL_BREADTH / 2.0,
+            false,
+        );
+        create_post_closure(
+            PITCH_RIGHT_LINE,
+            PITCH_TOP_LINE + PITCH_HEIGHT / 2.0 + GOAL_BREADTH / 2.0,
+            false,
+        );
+
+        goal_posts
+    }
+
+    pub(crate) fn create_stadium_walls(collider_set: &mut ColliderSet) {
+        let mut create_wall_closure = |width, height, x, y| {
+            let cuboid_collider = ColliderBuilder::cuboid(width / 2.0, height / 2.0)
+                .collision_groups(InteractionGroups::new(
+                    STADIUM_WALLS_GROUP,
+                    STADIUM_WALLS_GROUP,
+                ))
+                .translation(vector![x, y])
+                .build();
+            collider_set.insert(cuboid_collider);
+        };
+        // left stadium wall
+        create_wall_closure(0.0, STADIUM_HEIGHT, 0.0, STADIUM_HEIGHT / 2.0);
+
+        // right stadium wall
+        create_wall_closure(0.0, STADIUM_HEIGHT, STADIUM_WIDTH, STADIUM_HEIGHT / 2.0);
+
+        // top stadium wall
+        create_wall_closure(STADIUM_WIDTH, 0.0, STADIUM_WIDTH / 2.0, 0.0);
+
+        // bottom stadium wall
+        create_wall_closure(STADIUM_WIDTH, 0.0, STADIUM_WIDTH / 2.0, STADIUM_HEIGHT);
+    }
+
+    /// Halfway line and centre circle for each team,
+    /// they only collide with players of that team while the other one kicks off.
+    pub(crate) fn create_kickoff_barriers(
+        collider_set: &mut ColliderSet,
+    ) -> Vec<(ColliderHandle, bool)> {
+        let mut barriers = Vec::new();
+        for red in [true, false] {
+            let halfway_line =
+                ColliderBuilder::cuboid(PITCH_LINE_WIDTH / 2.0, STADIUM_HEIGHT / 2.0)
+                    .collision_groups(InteractionGroups::none())
+                    .translation(vector![STADIUM_WIDTH / 2.0, STADIUM_HEIGHT / 2.0])
+                    .build();
+            barriers.push((collider_set.insert(halfway_line), red));
+
+            let centre_circle = ColliderBuilder::ball(CENTRE_CIRCLE_RADIUS)
+                .collision_groups(InteractionGroups::none())
+                .translation(vector![STADIUM_WIDTH / 2.0, STADIUM_HEIGHT / 2.0])
+                .build();
+            barriers.push((collider_set.insert(centre_circle), red));
+        }
+        barriers
+    }
+
+    fn create_player(
+        &mut self,
+        x: f32,
+        y: f32,
+        is_red: bool,
+        number: usize,
+        nickname: &str,
+    ) -> Player {
+        let player_body_handle = Simulation::create_player_body(
+            &mut self.rigid_body_set,
+            &mut self.collider_set,
+            x,
+            y,
+            is_red,
+        );
+        self.next_player_id += 1;
+        Player::new(
+            player_body_handle,
+            self.next_player_id,
+            PLAYER_RADIUS,
+            is_red,
+            number,
+            sanitize_nickname(nickname),
+        )
+    }
+
+    pub(crate) fn create_player_body(
+        rigid_body_set: &mut RigidBodySet,
+        collider_set: &mut ColliderSet,
+        x: f32,
+        y: f32,
+        is_red: bool,
+    ) -> RigidBodyHandle {
+        let player_rigid_body = RigidBodyBuilder::new_dynamic()
+            .linear_damping(1.0)
+            .translation(vector![x, y])
+            .build();
+        let player_collider = ColliderBuilder::ball(PLAYER_RADIUS)
+            .collision_groups(Simulation::player_collision_groups(is_red))
+            .restitution(0.7)
+            .build();
+        let player_body_handle: RigidBodyHandle = rigid_body_set.insert(player_rigid_body);
+        collider_set.insert_with_parent(player_collider, player_body_handle, rigid_body_set);
+        player_body_handle
+    }
+
+    pub(crate) fn player_collision_groups(is_red: bool) -> InteractionGroups {
+        const COLLISION_GROUP: u32 =
+            PLAYERS_GROUP | STADIUM_WALLS_GROUP | BALL_GROUP | GOAL_POSTS_GROUP;
+        let collision_group = COLLISION_GROUP
+            | if is_red {
+                RED_KICKOFF_GROUP
+            } else {
+                BLUE_KICKOFF_GROUP
+            };
+        InteractionGroups::new(collision_group, collision_group)
+    }
+
+    fn create_ball(
+        rigid_body_set: &mut RigidBodySet,
+        collider_set: &mut ColliderSet,
+    ) -> RigidBodyHandle {
+        const COLLISION_GROUP: u32 =
+            BALL_GROUP | PLAYERS_GROUP | PITCH_LINES_GROUP | GOAL_POSTS_GROUP;
+
+        let ball_rigid_body = RigidBodyBuilder::new_dynamic()
+            .linear_damping(0.3)
+            .translation(vector![STADIUM_WIDTH / 2.0, STADIUM_HEIGHT / 2.0])
+            .build();
+        let ball_collider = ColliderBuilder::ball(BALL_RADIUS)
+            .density(0.5)
+            .collision_groups(InteractionGroups::new(COLLISION_GROUP, COLLISION_GROUP))
+            .restitution(0.7)
+            .build();
+        let ball_body_handle: RigidBodyHandle = rigid_body_set.insert(ball_rigid_body);
+        collider_set.insert_with_parent(ball_collider, ball_body_handle, rigid_body_set);
+
+        ball_body_handle
+    }
+
+    fn parse_input(&mut self) {
+        let shootout = self.arbiter.shootout.as_ref();
+        let mut players: Vec<_> = self.players.values_mut().collect();
+        players.extend(self.host_player.as_mut());
+        for player in players {
+            let player_last_tick_shot = player.last_tick_shot;
+            let input = player.get_input();
+            let body_handle = player.rigid_body_handle;
+
+            // during a penalty kick everyone except the shooter and the goalkeeper stands still
+            if shootout.is_some_and(|shootout| !shootout.can_move(body_handle)) {
+                self.rigid_body_set[body_handle].set_linvel(vector![0.0, 0.0], false);
+                continue;
+            }
+
+            if input.shoot {
+                if !player_last_tick_shot {
+                    let px;
+                    let py;
+                    {
+                        let player_body = &self.rigid_body_set[body_handle];
+                        px = player_body.translation().x;
+                        py = player_body.translation().y;
+                    }
+
+                    let ball_body = &mut self.rigid_body_set[self.ball_body_handle];
+                    let bx = ball_body.translation().x;
+                    let by = ball_body.translation().y;
+
+                    let dx = bx - px;
+                    let dy = by - py;
+                    let dist_sqr = dx * dx + dy * dy;
+                    if dist_sqr <= SHOOTING_DISTANCE * SHOOTING_DISTANCE {
+                        let angle = crate::game::utils::angle(px, py, bx, by);
+                        let x_speed =
+                            BALL_TOP_SPEED * (std::f32::consts::PI * (angle / 180.0)).cos();
+                        let y_speed =
+                            BALL_TOP_SPEED * (std::f32::consts::PI * (angle / 180.0)).sin();
+                        ball_body.set_linvel(vector![x_speed, y_speed], true);
+                    }
+                    player.set_last_tick_shot(true);
+                }
+            } else {
+                player.set_last_tick_shot(false);
+            }
+
+            Simulation::move_player(&mut self.rigid_body_set[body_handle], input);
+        }
+    }
+
+    /// Pushes player's body in the directions they hold, up to their top speed.
+    pub(crate) fn move_player(player_body: &mut RigidBody, input: PlayerInput) {
+        if input.up {
+            player_body.apply_impulse(vector![0.0, -PLAYER_ACCELERATION], true);
+        } else if input.down {
+            player_body.apply_impulse(vector![0.0, PLAYER_ACCELERATION], true);
+        }
+
+        if input.left {
+            player_body.apply_impulse(vector![-PLAYER_ACCELERATION, 0.0], true);
+        } else if input.right {
+            player_body.apply_impulse(vector![PLAYER_ACCELERATION, 0.0], true);
+        }
+
+        Simulation::limit_speed(player_body, PLAYER_TOP_SPEED);
+    }
+
+    fn limit_speed(rigid_body: &mut RigidBody, top_speed: f32) {
+        let x_speed = rigid_body.linvel().x;
+        let y_speed = rigid_body.linvel().y;
+        let speed = f32::sqrt(x_speed * x_speed + y_speed * y_speed);
+        if speed > top_speed {
+            let speed_normalized = rigid_body.linvel().normalize();
+            rigid_body.set_linvel(
+                vector![
+                    speed_normalized.x * top_speed,
+                    speed_normalized.y * top_speed
+                ],
+                true,
+            );
+        }
+    }
+
+    fn check_timer(&mut self) {
+        if self.arbiter.game_ended {
+            return;
+        }
+        if self.arbiter.shootout.is_some() {
+            self.shootout_tick();
+            return;
+        }
+        self.arbiter.clock_tick();
+        if self.arbiter.reset_timer > 0 {
+            self.timer_tick();
+        } else if self.goal_scored() {
+            self.arbiter.reset_timer = RESET_TIME;
+        } else {
+            self.check_ending();
+        }
+    }
+
+    fn goal_scored(&mut self) -> bool {
+        let ball_body = &mut self.rigid_body_set[self.ball_body_handle];
+        let x = ball_body.translation().x;
+        if x < PITCH_LEFT_LINE {
+            self.arbiter.set_blue_scored();
+            self.events
+                .push(SimulationEvent::GoalScored { red_scored: false });
+            true
+        } else if x > PITCH_RIGHT_LINE {
+            self.arbiter.set_red_scored();
+            self.events
+                .push(SimulationEvent::GoalScored { red_scored: true });
+            true
+        } else {
+            false
+        }
+    }
+
+    fn timer_tick(&mut self) {
+        self.arbiter.reset_timer -= 1;
+        if self.arbiter.reset_timer == 0 {
+            self.arbiter.reset_who_scored();
+            self.check_ending();
+            self.reset_game();
+        }
+    }
+
+    fn check_ending(&mut self) {
+        if self.arbiter.check_ending() {
+            self.events.push(SimulationEvent::GameEnded);
+        }
+    }
+
+    fn shootout_tick(&mut self) {
+        let ball_x = self.rigid_body_set[self.ball_body_handle].translation().x;
+        let shootout = match self.arbiter.shootout.as_mut() {
+            Some(shootout) => shootout,
+            None => return,
+        };
+        match shootout.phase {
+            PenaltyPhase::Setup => self.setup_penalty_kick(),
+            PenaltyPhase::Kicking(ticks_left) => {
+                let red_kicking = shootout.red_kicking;
+                // red attacks the right goal, blue the left one
+                let scored = if red_kicking {
+                    ball_x > PITCH_RIGHT_LINE
+                } else {
+                    ball_x < PITCH_LEFT_LINE
+                };
+                if scored || ticks_left == 0 {
+                    shootout.record_kick(scored);
+                    self.events.push(SimulationEvent::PenaltyTaken {
+                        red_kicking,
+                        scored,
+                    });
+                } else {
+                    shootout.phase = PenaltyPhase::Kicking(ticks_left - 1);
+                }
+            }
+            PenaltyPhase::Result(ticks_left) => {
+                if ticks_left > 0 {
+                    shootout.phase = PenaltyPhase::Result(ticks_left - 1);
+                } else if shootout.decided() {
+                    self.arbiter.game_ended = true;
+                    self.events.push(SimulationEvent::GameEnded);
+                } else {
+                    shootout.next_kick();
+                }
+            }
+        }
+    }
+
+    /// Places the ball on the penalty spot, the shooter behind it,
+    /// the opposing goalkeeper on the goal line and everyone else by the halfway line.
+    fn setup_penalty_kick(&mut self) {
+        self.set_kickoff(None);
+        let shootout = match self.arbiter.shootout.as_mut() {
+            Some(shootout) => shootout,
+            None => return,
+        };
+        let red_kicking = shootout.red_kicking;
+
+        let mut kickers: Vec<&Player> = self
+            .players
+            .values()
+            .chain(self.host_player.iter())
+            .filter(|player| player.red == red_kicking)
+            .collect();
+        kickers.sort_by_key(|player| player.number);
+        let mut goalkeepers: Vec<&Player> = self
+            .players
+            .values()
+            .chain(self.host_player.iter())
+            .filter(|player| player.red != red_kicking)
+            .collect();
+        goalkeepers.sort_by_key(|player| player.number);
+
+        // every player of the kicking team takes their turn
+        let shooter = if kickers.is_empty() {
+            None
+        } else {
+            Some(kickers[shootout.kicks_taken() as usize % kickers.len()])
+        };
+        let goalkeeper = goalkeepers.first().copied();
+        shootout.shooter = shooter.map(|player| player.rigid_body_handle);
+        shootout.goalkeeper = goalkeeper.map(|player| player.rigid_body_handle);
+        shootout.shooter_number = shooter.map_or(-1, |player| player.number as i32);
+        shootout.phase = PenaltyPhase::Kicking(PENALTY_KICK_TIME);
+        self.events.push(SimulationEvent::PenaltyKick {
+            red_kicking,
+            shooter_number: shootout.shooter_number,
+            round: shootout.kicks_taken() + 1,
+        });
+
+        let (goal_line, direction) = if red_kicking {
+            (PITCH_RIGHT_LINE, 1.0)
+        } else {
+            (PITCH_LEFT_LINE, -1.0)
+        };
+        let spot_x = goal_line - direction * PENALTY_SPOT_DISTANCE;
+        let middle_y = STADIUM_HEIGHT / 2.0;
+        {
+            let ball_body = &mut self.rigid_body_set[self.ball_body_handle];
+            ball_body.set_position(Isometry::new(vector![spot_x, middle_y], 0.0), false);
+            ball_body.set_linvel(vector![0.0, 0.0], false);
+        }
+
+        let shooter_handle = shootout.shooter;
+        let goalkeeper_handle = shootout.goalkeeper;
+        let mut red_benched = 0.0;
+        let mut blue_benched = 0.0;
+        for player in self.players.values().chain(self.host_player.iter()) {
+            let (x, y) = if Some(player.rigid_body_handle) == shooter_handle {
+                (spot_x - direction * 2.0 * PLAYER_DIAMETER, middle_y)
+            } else if Some(player.rigid_body_handle) == goalkeeper_handle {
+                (goal_line - direction * PLAYER_DIAMETER, middle_y)
+            } else if player.red {
+                red_benched += 1.0;
+                (
+                    STADIUM_WIDTH / 2.0 - PLAYER_DIAMETER,
+                    PITCH_TOP_LINE + red_benched * PLAYER_DIAMETER,
+                )
+            } else {
+                blue_benched += 1.0;
+                (
+                    STADIUM_WIDTH / 2.0 + PLAYER_DIAMETER,
+                    PITCH_TOP_LINE + blue_benched * PLAYER_DIAMETER,
+                )
+            };
+            player.set_position(&mut self.rigid_body_set, x, y);
+        }
+    }
+
+    /// Kickoff is taken as soon as the ball leaves the centre spot,
+    /// which only the kicking team can reach.
+    fn check_kickoff(&mut self) {
+        if self.arbiter.kickoff.is_none() {
+            return;
+        }
+        let ball_position = self.rigid_body_set[self.ball_body_handle].translation();
+        let dx = ball_position.x - STADIUM_WIDTH / 2.0;
+        let dy = ball_position.y - STADIUM_HEIGHT / 2.0;
+        if dx * dx + dy * dy > KICKOFF_BALL_DISTANCE * KICKOFF_BALL_DISTANCE {
+            self.set_kickoff(None);
+        }
+    }
+
+    fn set_kickoff(&mut self, kickoff: Option<bool>) {
+        self.arbiter.kickoff = kickoff;
+        self.update_kickoff_barriers();
+    }
+
+    fn update_kickoff_barriers(&mut self) {
+        Simulation::set_kickoff_barriers(
+            &mut self.collider_set,
+            &self.kickoff_barriers,
+            self.arbiter.kickoff,
+        );
+    }
+
+    /// Makes the barriers of the team not taking `kickoff` solid for its players, and the rest not.
+    pub(crate) fn set_kickoff_barriers(
+        collider_set: &mut ColliderSet,
+        barriers: &[(ColliderHandle, bool)],
+        kickoff: Option<bool>,
+    ) {
+        for &(collider_handle, red) in barriers {
+            let groups = if kickoff == Some(!red) {
+                let group = if red {
+                    RED_KICKOFF_GROUP
+                } else {
+                    BLUE_KICKOFF_GROUP
+                };
+                InteractionGroups::new(group, group)
+            } else {
+                InteractionGroups::none()
+            };
+            collider_set[collider_handle].set_collision_groups(groups);
+        }
+    }
+
+    fn reset_game(&mut self) {
+        {
+            let ball_body = &mut self.rigid_body_set[self.ball_body_handle];
+            ball_body.set_position(
+                Isometry::new(vector![STADIUM_WIDTH / 2.0, STADIUM_HEIGHT / 2.0], 0.0),
+                false,
+            );
+            ball_body.set_linvel(vector![0.0, 0.0], false);
+        }
+
+        // players line up in their team's formation in the order of their numbers
+        for red in [true, false] {
+            let mut team: Vec<&Player> = self
+                .players
+                .values()
+                .chain(self.host_player.iter())
+                .filter(|player| player.red == red)
+                .collect();
+            team.sort_by_key(|player| player.number);
+            let team_size = team.len();
+            for (slot, player) in team.into_iter().enumerate() {
+                player.reset_position(&mut self.rigid_body_set, slot, team_size);
+            }
+        }
+
+        // team that conceded kicks off, but there are no kickoffs in a penalty shootout
+        if self.arbiter.shootout.is_none() {
+            self.set_kickoff(Some(self.arbiter.next_kickoff_red));
+        }
+    }
+
+    /// Player of given user, or the host's player for `None`.
+    pub fn get_player(&self, user_id: Option<&UserId>) -> Option<&Player> {
+        match user_id {
+            Some(user_id) => self.players.get(user_id),
+            None => self.host_player.as_ref(),
+        }
+    }
+
+    /// Every player along with their user, which is `None` for the host.
+    pub fn get_lineup(&self) -> Vec<(Option<UserId>, &Player)> {
+        self.players
+            .iter()
+            .map(|(&user_id, player)| (Some(user_id), player))
+            .chain(self.host_player.iter().map(|player| (None, player)))
+            .collect()
+    }
+
+    pub fn get_player_entities(&self) -> Vec<Circle> {
+        self.players
+            .values()
+            .chain(self.host_player.iter())
+            .map(|player| player.to_circle(&self.rigid_body_set))
+            .collect()
+    }
+
+    /// Position and velocity of a user's player, for their client to predict its movement.
+    pub fn get_player_body(&self, user_id: &UserId) -> Option<PlayerBody> {
+        let player = self.players.get(user_id)?;
+        let body = &self.rigid_body_set[player.rigid_body_handle];
+        Some(PlayerBody {
+            id: player.id,
+            x: body.translation().x,
+            y: body.translation().y,
+            x_speed: body.linvel().x,
+            y_speed: body.linvel().y,
+        })
+    }
+
+    /// Ids of the players, in the order of `get_player_entities`.
+    pub fn get_player_ids(&self) -> Vec<u32> {
+        self.players
+            .values()
+            .chain(self.host_player.iter())
+            .map(|player| player.id)
+            .collect()
+    }
+
+    pub fn get_ball_entity(&self) -> Circle {
+        let brb = &self.rigid_body_set[self.ball_body_handle];
+        Circle::new(
+            brb.translation().x,
+            brb.translation().y,
+            BALL_RADIUS,
+            false,
+            -1,
+        )
+    }
+
+    pub fn get_edge_entities(&self) -> Vec<Edge> {
+        self.edges.clone()
+    }
+
+    pub fn get_goal_posts_entities(&self) -> Vec<Circle> {
+        self.goal_posts.clone()
+    }
+
+    pub fn get_score(&self) -> Score {
+        Score::new(self.arbiter.red_score, self.arbiter.blue_score)
+    }
+
+    pub fn get_tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn get_game_ended(&self) -> bool {
+        self.arbiter.game_ended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::constants::MAX_GOALS;
+
+    fn put_ball(simulation: &mut Simulation, x: f32) {
+        let ball_body = &mut simulation.rigid_body_set[simulation.ball_body_handle];
+        ball_body.set_position(Isometry::new(vector![x, STADIUM_HEIGHT / 2.0], 0.0), true);
+        ball_body.set_linvel(vector![0.0, 0.0], true);
+    }
+
+    fn step(simulation: &mut Simulation) -> Snapshot {
+        simulation.step(PlayerInput::default(), &HashMap::new())
+    }
+
+    #[test]
+    fn tick_advances_once_per_step() {
+        let mut simulation = Simulation::default();
+        for expected in 1..=5 {
+            assert_eq!(step(&mut simulation).tick, expected);
+        }
+        assert_eq!(simulation.get_tick(), 5);
+    }
+
+    #[test]
+    fn ball_past_the_goal_line_scores() {
+        let mut simulation = Simulation::default();
+        put_ball(&mut simulation, PITCH_RIGHT_LINE + BALL_RADIUS);
+        let snapshot = step(&mut simulation);
+        assert_eq!(snapshot.score.red_score, 1);
+        assert_eq!(snapshot.score.blue_score, 0);
+        assert!(snapshot.red_scored);
+        assert_eq!(
+            snapshot.events,
+            vec![SimulationEvent::GoalScored { red_scored: true }]
+        );
+
+        // the ball stays in the goal until the kickoff, without scoring again
+        for _ in 0..RESET_TIME {
+            assert_eq!(step(&mut simulation).score.red_score, 1);
+        }
+        assert_eq!(simulation.arbiter.kickoff, Some(false));
+
+        put_ball(&mut simulation, PITCH_LEFT_LINE - BALL_RADIUS);
+        assert_eq!(step(&mut simulation).score.blue_score, 1);
+    }
+
+    #[test]
+    fn match_ends_at_the_goal_limit() {
+        let mut simulation = Simulation::default();
+        let mut events = Vec::new();
+        for goal in 1..=MAX_GOALS {
+            assert!(!simulation.get_game_ended());
+            put_ball(&mut simulation, PITCH_RIGHT_LINE + BALL_RADIUS);
+            assert_eq!(step(&mut simulation).score.red_score, goal);
+            for _ in 0..RESET_TIME {
+                events.append(&mut step(&mut simulation).events);
+            }
+        }
+        assert!(simulation.get_game_ended());
+        assert_eq!(events, vec![SimulationEvent::GameEnded]);
+
+        // nothing counts once the match is over
+        put_ball(&mut simulation, PITCH_LEFT_LINE - BALL_RADIUS);
+        assert_eq!(step(&mut simulation).score.blue_score, 0);
+    }
+}
@@ -1,8 +1,25 @@
-use crate::game::constants::{PITCH_LEFT_LINE, PITCH_RIGHT_LINE, PLAYER_DIAMETER, STADIUM_HEIGHT};
+use crate::game::constants::{
+    EXTRA_TIME, MAX_CHAT_MESSAGE_LENGTH, MAX_GOALS, MAX_NICKNAME_LENGTH, TICKS_PER_SECOND,
+};
+use crate::game::delta::{NetworkState, StateDelta};
+use crate::game::formations::kickoff_position;
 use crate::game::input::PlayerInput;
+use crate::game::migration::MigrationSnapshot;
+use crate::game::shootout::Shootout;
 use rapier2d::dynamics::{RigidBodyHandle, RigidBodySet};
 use rapier2d::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Trims nickname and cuts it to `MAX_NICKNAME_LENGTH` characters, as it comes from players.
+pub fn sanitize_nickname(nickname: &str) -> String {
+    nickname.trim().chars().take(MAX_NICKNAME_LENGTH).collect()
+}
+
+/// Trims chat message and cuts it to `MAX_CHAT_MESSAGE_LENGTH` characters.
+pub fn sanitize_chat_message(text: &str) -> String {
+    text.trim().chars().take(MAX_CHAT_MESSAGE_LENGTH).collect()
+}
 
 pub fn angle(x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
     const RADIAN: f32 = 180.0 / std::f32::consts::PI;
@@ -15,9 +32,12 @@ pub fn angle(x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
 #[derive(Clone)]
 pub struct Player {
     pub rigid_body_handle: RigidBodyHandle,
+    /// Identifies the player in network state updates for as long as they are in the match.
+    pub id: u32,
     pub radius: f32,
     pub red: bool,
     pub number: usize,
+    pub nickname: String,
     pub current_input: PlayerInput,
     pub last_tick_shot: bool,
 }
@@ -25,15 +45,19 @@ pub struct Player {
 impl Player {
     pub fn new(
         rigid_body_handle: RigidBodyHandle,
+        id: u32,
         radius: f32,
         red: bool,
         number: usize,
+        nickname: String,
     ) -> Player {
         Player {
             rigid_body_handle,
+            id,
             radius,
             red,
             number,
+            nickname,
             current_input: PlayerInput::default(),
             last_tick_shot: false,
         }
@@ -45,13 +69,16 @@ impl Player {
 
     pub fn to_circle(&self, rigid_body_set: &RigidBodySet) -> Circle {
         let rb = &rigid_body_set[self.rigid_body_handle];
-        Circle::new(
-            rb.translation().x,
-            rb.translation().y,
-            self.radius,
-            self.red,
-            self.number as i32,
-        )
+        Circle {
+            nickname: self.nickname.clone(),
+            ..Circle::new(
+                rb.translation().x,
+                rb.translation().y,
+                self.radius,
+                self.red,
+                self.number as i32,
+            )
+        }
     }
 
     pub fn set_input(&mut self, input: PlayerInput) {
@@ -62,25 +89,17 @@ impl Player {
         self.current_input
     }
 
-    pub fn reset_position(
-        &mut self,
-        rigid_body_set: &mut RigidBodySet,
-        x_offset: f32,
-        y_offset: f32,
-    ) {
-        let mut x = x_offset;
-        let mut y = y_offset;
-        if self.red {
-            x += PITCH_LEFT_LINE + 2.0 * PLAYER_DIAMETER;
-            y += STADIUM_HEIGHT / 2.0
-        } else {
-            x += PITCH_RIGHT_LINE - 2.0 * PLAYER_DIAMETER;
-            y += STADIUM_HEIGHT / 2.0
-        }
+    pub fn set_position(&self, rigid_body_set: &mut RigidBodySet, x: f32, y: f32) {
         let player_body = &mut rigid_body_set[self.rigid_body_handle];
         player_body.set_position(Isometry::new(vector![x, y], 0.0), false);
         player_body.set_linvel(vector![0.0, 0.0], false);
     }
+
+    /// Moves the player to their kickoff spot, `slot` being their place in a team of `team_size`.
+    pub fn reset_position(&self, rigid_body_set: &mut RigidBodySet, slot: usize, team_size: usize) {
+        let (x, y) = kickoff_position(self.red, slot, team_size);
+        self.set_position(rigid_body_set, x, y);
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -90,6 +109,9 @@ pub struct Circle {
     pub radius: f32,
     pub red: bool,
     pub player_number: i32,
+    // only players have a nickname, replays recorded before nicknames have none at all
+    #[serde(default)]
+    pub nickname: String,
 }
 
 impl Circle {
@@ -100,8 +122,24 @@ impl Circle {
             radius,
             red,
             player_number,
+            nickname: String::new(),
         }
     }
+
+    /// Whether the circle can be drawn, with a finite position and a positive, finite radius.
+    pub fn is_valid(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.radius.is_finite() && self.radius > 0.0
+    }
+}
+
+/// Position and velocity of a player's body, as the host simulates it.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct PlayerBody {
+    pub id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub x_speed: f32,
+    pub y_speed: f32,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -123,9 +161,38 @@ impl Edge {
             white,
         }
     }
+
+    pub fn is_valid(&self) -> bool {
+        [self.x, self.y, self.width, self.height]
+            .iter()
+            .all(|value| value.is_finite())
+            && self.width >= 0.0
+            && self.height >= 0.0
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Entry of the pre-match lobby list.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LobbyPlayer {
+    pub red: bool,
+    pub number: i32,
+    pub nickname: String,
+    pub ready: bool,
+    pub is_host: bool,
+}
+
+/// Chat message along with its author, as shown to players.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChatLine {
+    pub red: bool,
+    pub player_number: i32,
+    pub nickname: String,
+    pub text: String,
+    /// Only the author's team got the message.
+    pub team_only: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Score {
     pub(crate) red_score: u32,
     pub(crate) blue_score: u32,
@@ -139,37 +206,131 @@ impl Score {
         }
     }
 }
+
+/// What happens when a timed match ends with both teams level.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    #[default]
+    Draw,
+    /// Overtime without a clock, the next goal wins.
+    GoldenGoal,
+    /// Overtime lasting `EXTRA_TIME`, after which the match may still end in a draw.
+    ExtraTime,
+    Penalties,
+}
+
+impl FromStr for TieBreak {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "draw" => Ok(TieBreak::Draw),
+            "golden_goal" => Ok(TieBreak::GoldenGoal),
+            "extra_time" => Ok(TieBreak::ExtraTime),
+            "penalties" => Ok(TieBreak::Penalties),
+            _ => Err(format!("unknown tie-break: {s}")),
+        }
+    }
+}
+
+/// Rules of a match chosen by the host before it starts.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct MatchSettings {
+    /// Length of the match in seconds, `None` means playing until `MAX_GOALS`.
+    pub duration: Option<u32>,
+    pub tie_break: TieBreak,
+}
+
 pub struct Arbiter {
     pub red_scored: bool,
     pub blue_scored: bool,
     pub red_score: u32,
     pub blue_score: u32,
-    pub send_score_message: bool,
     pub reset_timer: u32,
     pub game_ended: bool,
+    /// Ticks until the end of a timed match.
+    pub time_left: Option<u32>,
+    pub tie_break: TieBreak,
+    pub overtime: bool,
+    pub shootout: Option<Shootout>,
+    /// Team taking the kickoff in progress, true for red.
+    pub kickoff: Option<bool>,
+    pub next_kickoff_red: bool,
 }
 
 impl Arbiter {
-    pub fn new() -> Arbiter {
+    pub fn new(settings: MatchSettings) -> Arbiter {
         Arbiter {
             red_scored: false,
             blue_scored: false,
             red_score: 0,
             blue_score: 0,
-            send_score_message: false,
             reset_timer: 0,
             game_ended: false,
+            time_left: settings
+                .duration
+                .map(|seconds| seconds.saturating_mul(TICKS_PER_SECOND)),
+            tie_break: settings.tie_break,
+            overtime: false,
+            shootout: None,
+            kickoff: Some(true),
+            next_kickoff_red: true,
+        }
+    }
+    /// Decides whether the match is over, starting overtime when a timed match ends level.
+    /// Returns true only on the tick the match ended.
+    pub fn check_ending(&mut self) -> bool {
+        if self.game_ended || self.shootout.is_some() {
+            return false;
+        }
+        let decided = self.red_score != self.blue_score;
+        self.game_ended = if self.red_score == MAX_GOALS || self.blue_score == MAX_GOALS {
+            true
+        } else if self.overtime && self.tie_break == TieBreak::GoldenGoal {
+            decided
+        } else if self.time_is_up() {
+            if decided || self.overtime {
+                true
+            } else {
+                self.start_overtime()
+            }
+        } else {
+            false
+        };
+        self.game_ended
+    }
+    /// Returns true if the match should end as a draw instead.
+    fn start_overtime(&mut self) -> bool {
+        match self.tie_break {
+            TieBreak::Draw => return true,
+            TieBreak::GoldenGoal => self.time_left = None,
+            TieBreak::ExtraTime => self.time_left = Some(EXTRA_TIME * TICKS_PER_SECOND),
+            TieBreak::Penalties => {
+                self.shootout = Some(Shootout::new());
+                return false;
+            }
+        }
+        self.overtime = true;
+        false
+    }
+    pub fn clock_tick(&mut self) {
+        if let Some(time_left) = self.time_left.as_mut() {
+            *time_left = time_left.saturating_sub(1);
         }
     }
+    pub fn time_is_up(&self) -> bool {
+        self.time_left == Some(0)
+    }
     pub fn set_red_scored(&mut self) {
         self.red_scored = true;
         self.red_score += 1;
-        self.send_score_message = true;
+        self.next_kickoff_red = false;
     }
     pub fn set_blue_scored(&mut self) {
         self.blue_scored = true;
         self.blue_score += 1;
-        self.send_score_message = true;
+        self.next_kickoff_red = true;
     }
     pub fn reset_who_scored(&mut self) {
         self.blue_scored = false;
@@ -177,21 +338,123 @@ impl Arbiter {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub enum Message {
+    /// Everything a player who has just joined needs, including the score and whether
+    /// the match is already over, as they may be coming back in the middle of it.
     GameInit {
         edges: Vec<Edge>,
         goal_posts: Vec<Circle>,
         players: Vec<Circle>,
         ball: Circle,
+        score: Score,
+        game_ended: bool,
+        /// Whether the match is under way, otherwise players are still in the lobby.
+        // replays recorded before it was sent start with the match
+        #[serde(default = "game_started_default")]
+        game_started: bool,
     },
+    /// Full game state as recorded in replays, players are sent keyframes and deltas instead.
     GameState {
         players: Vec<Circle>,
         ball: Circle,
+        time_left: Option<u32>,
+        overtime: bool,
+    },
+    /// Full game state, for a player without an acknowledged state the host still has.
+    /// Along with game states, players get their own body and the sequence number
+    /// of their last input the host received, to reconcile their prediction with.
+    Keyframe {
+        state: NetworkState,
+        player_body: Option<PlayerBody>,
+        input_ack: Option<u32>,
+    },
+    StateDelta {
+        delta: StateDelta,
+        player_body: Option<PlayerBody>,
+        input_ack: Option<u32>,
     },
     GoalScored {
         red_scored: bool,
         score: Score,
     },
     GameEnded,
+    PenaltyKick {
+        red_kicking: bool,
+        shooter_number: i32,
+        round: u32,
+    },
+    PenaltyTaken {
+        red_kicking: bool,
+        scored: bool,
+        penalties: Score,
+    },
+    PlayerLeft {
+        red: bool,
+        player_number: i32,
+    },
+    RematchVotes {
+        votes: u32,
+        players: u32,
+    },
+    /// Finished match starts over, with the score and clock reset.
+    Rematch,
+    /// Players waiting for the match to start, sent whenever someone joins, leaves or gets ready.
+    Lobby {
+        players: Vec<LobbyPlayer>,
+    },
+    MatchStarted,
+    Chat {
+        line: ChatLine,
+    },
+    /// Latency measurement, to be answered with a pong carrying the same time.
+    Ping {
+        sent_at: f64,
+    },
+    /// State of the match to carry on with, should the host leave.
+    Migration {
+        snapshot: MigrationSnapshot,
+    },
+    /// Host hasn't heard from the player for too long and took them out of the match,
+    /// they have to shake hands and introduce themselves again to come back.
+    Dropped,
+}
+
+fn game_started_default() -> bool {
+    true
+}
+
+/// Messages players send to the host.
+#[derive(Serialize, Deserialize, Clone)]
+pub enum ClientMessage {
+    /// First message on a new connection, `reconnect_token` identifies the player's browser.
+    /// After a host migration, `player_id` is the id the player had under the previous host.
+    Hello {
+        reconnect_token: String,
+        nickname: String,
+        player_id: Option<u32>,
+    },
+    /// Sent every simulation step with the player's latest inputs, oldest first.
+    /// `sequence` numbers the last of them and `ack` is the sequence of the newest game state
+    /// the player holds.
+    Input {
+        inputs: Vec<PlayerInput>,
+        sequence: u32,
+        ack: Option<u32>,
+    },
+    /// Player got a delta against a state they don't have, so they need a full one.
+    KeyframeRequest,
+    /// Player wants to play again once the match has ended.
+    RematchVote,
+    /// Player is (no longer) ready for the match to start.
+    Ready {
+        ready: bool,
+    },
+    Chat {
+        text: String,
+        team_only: bool,
+    },
+    Pong {
+        sent_at: f64,
+    },
 }
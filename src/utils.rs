@@ -1,5 +1,55 @@
-use web_sys::Window;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{Blob, BlobPropertyBag, HtmlAnchorElement, Url, Window};
+
+const RECONNECT_TOKEN_KEY: &str = "footballers_reconnect_token";
 
 pub fn global_window() -> Window {
     web_sys::window().expect("there was no window global object!")
 }
+
+/// Monotonic time in seconds, for measuring intervals between frames.
+pub fn now_seconds() -> f64 {
+    global_window()
+        .performance()
+        .expect("performance should be available on window")
+        .now()
+        / 1000.0
+}
+
+/// Makes the browser download `contents` as a file with given name.
+pub fn download_file(file_name: &str, contents: &str, mime_type: &str) -> Result<(), JsValue> {
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let blob =
+        Blob::new_with_str_sequence_and_options(&parts, BlobPropertyBag::new().type_(mime_type))?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let document = global_window()
+        .document()
+        .ok_or_else(|| JsValue::from("document node is missing"))?;
+    let anchor = document
+        .create_element("a")?
+        .dyn_into::<HtmlAnchorElement>()?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    Url::revoke_object_url(&url)
+}
+
+/// Token identifying this browser to the match host, so a player rejoining gets their place back.
+/// It's kept in local storage, without it every page load gets a new one.
+pub fn reconnect_token() -> String {
+    let storage = global_window().local_storage().ok().flatten();
+    if let Some(token) = storage
+        .as_ref()
+        .and_then(|storage| storage.get_item(RECONNECT_TOKEN_KEY).ok().flatten())
+    {
+        return token;
+    }
+    let random_part = || (js_sys::Math::random() * u32::MAX as f64) as u32;
+    let token = format!("{:08x}{:08x}", random_part(), random_part());
+    if let Some(storage) = storage {
+        let _ = storage.set_item(RECONNECT_TOKEN_KEY, &token);
+    }
+    token
+}